@@ -0,0 +1,79 @@
+//! Compares checking every projectile against every enemy with only checking
+//! the ones in nearby cells of the broad phase grid.
+//!
+//! `cargo bench --bench collisions` steps a world with thousands of enemies
+//! and projectiles kept in play, topping them up from random places as they
+//! get shot or leave the arena.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use macroquad::math::Vec2;
+
+use flowfield::enemy::Enemy;
+use flowfield::map::builtin_maps;
+use flowfield::rng::Rng;
+use flowfield::sim::{Body, Input, World, ARENA, ENEMY_INIT_SPEED, PROJECTILE_INIT_SPEED, TICK_DT};
+use flowfield::weapon::{Projectile, ProjectileKind};
+
+const BODIES: [(usize, usize); 4] = [(100, 100), (500, 500), (1000, 2000), (3000, 3000)];
+const TICKS: usize = 120;
+
+fn main() {
+    println!(
+        "{:<20} {:>12} {:>12} {:>8}",
+        "enemies x shots", "all pairs/s", "grid/s", "speedup"
+    );
+    for (enemies, projectiles) in BODIES {
+        let naive = run_ticks(enemies, projectiles, false);
+        let grid = run_ticks(enemies, projectiles, true);
+        let rate = |time: Duration| TICKS as f64 / time.as_secs_f64();
+        println!(
+            "{:<20} {:>12.0} {:>12.0} {:>7.1}x",
+            format!("{} x {}", enemies, projectiles),
+            rate(naive),
+            rate(grid),
+            naive.as_secs_f64() / grid.as_secs_f64()
+        );
+    }
+}
+
+fn random_pos(rng: &mut Rng) -> Vec2 {
+    let x = (rng.rand() % ARENA.x as u32) as f32;
+    let y = (rng.rand() % ARENA.y as u32) as f32;
+    Vec2::new(x, y)
+}
+
+/// Steps a world on the first built-in map with `enemies` and `projectiles`
+/// of each in play the whole time. Enemies are kept small so that most
+/// projectiles miss and the numbers stay up.
+fn run_ticks(enemies: usize, projectiles: usize, broad_phase: bool) -> Duration {
+    let mut map = builtin_maps().swap_remove(0);
+    map.enemies.max = enemies;
+    map.enemies.radius = 5.0;
+    map.time_limit = f32::INFINITY;
+    let mut world = World::new(map, 0);
+    world.broad_phase = broad_phase;
+    world.start();
+    let mut rng = Rng::new(0);
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        while world.enemies.len() < enemies {
+            let pos = random_pos(&mut rng);
+            let vel = Vec2::from_angle(pos.y) * ENEMY_INIT_SPEED;
+            let body = Body::new(pos, vel).with_physics(world.map.enemies.physics);
+            world
+                .enemies
+                .push(Enemy::new(body, world.map.enemies.radius));
+        }
+        while world.projectiles.len() < projectiles {
+            let pos = random_pos(&mut rng);
+            let vel = Vec2::from_angle(pos.x) * PROJECTILE_INIT_SPEED;
+            world
+                .projectiles
+                .push(Projectile::new(pos, vel, ProjectileKind::Standard));
+        }
+        black_box(world.step(TICK_DT, &Input::default()));
+    }
+    start.elapsed()
+}
@@ -0,0 +1,127 @@
+//! Compares evaluating fields directly with looking them up on a baked grid.
+//!
+//! `cargo bench --bench field` runs every built-in map, plus an animated one
+//! that has to be rebaked as it goes, with a few hundred projectiles kept in
+//! flight.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use macroquad::math::Vec2;
+
+use flowfield::field::VectorField;
+use flowfield::grid::FieldGrid;
+use flowfield::map::{builtin_maps, BakeSettings, Map};
+use flowfield::rng::Rng;
+use flowfield::sim::{Input, World, ARENA, PROJECTILE_INIT_SPEED, TICK_DT};
+use flowfield::weapon::{Projectile, ProjectileKind};
+
+const PROJECTILES: [usize; 3] = [100, 300, 1000];
+const TICKS: usize = 600;
+const SAMPLES: usize = 1_000_000;
+
+const ANIMATED_MAP: &str = r#"{
+  "name": "animated",
+  "field": {
+    "type": "blend",
+    "period": 8.0,
+    "from": { "type": "curl_noise", "seed": 1, "zoom": 400.0, "strength": 1000.0, "scroll": [50.0, 0.0] },
+    "to": { "type": "pulse", "strength": 1000.0, "period": 2.0 }
+  }
+}"#;
+
+fn main() {
+    let mut maps = builtin_maps();
+    maps.push(Map::from_json(ANIMATED_MAP).expect("the animated map is valid"));
+
+    println!(
+        "{:<14} {:>14} {:>14} {:>8}",
+        "samples", "direct/s", "baked/s", "speedup"
+    );
+    for map in &maps {
+        let (direct, baked) = (sample_direct(map), sample_baked(map));
+        print_row(&map.name, SAMPLES, direct, baked);
+    }
+
+    println!();
+    println!(
+        "{:<14} {:>14} {:>14} {:>8}",
+        "ticks", "direct/s", "baked/s", "speedup"
+    );
+    for map in &maps {
+        for projectiles in PROJECTILES {
+            let direct = run_ticks(map.clone(), projectiles);
+            let baked = run_ticks(
+                Map {
+                    bake: Some(BakeSettings::default()),
+                    ..map.clone()
+                },
+                projectiles,
+            );
+            print_row(
+                &format!("{} x{}", map.name, projectiles),
+                TICKS,
+                direct,
+                baked,
+            );
+        }
+    }
+}
+
+fn print_row(name: &str, count: usize, direct: Duration, baked: Duration) {
+    let rate = |time: Duration| count as f64 / time.as_secs_f64();
+    println!(
+        "{:<14} {:>14.0} {:>14.0} {:>7.1}x",
+        name,
+        rate(direct),
+        rate(baked),
+        direct.as_secs_f64() / baked.as_secs_f64()
+    );
+}
+
+fn positions() -> impl Iterator<Item = Vec2> {
+    let mut rng = Rng::new(0);
+    std::iter::repeat_with(move || {
+        let x = (rng.rand() % ARENA.x as u32) as f32 - ARENA.x / 2.0;
+        let y = (rng.rand() % ARENA.y as u32) as f32 - ARENA.y / 2.0;
+        Vec2::new(x, y)
+    })
+}
+
+fn sample_direct(map: &Map) -> Duration {
+    let start = Instant::now();
+    for (i, pos) in positions().take(SAMPLES).enumerate() {
+        black_box(map.field.force(pos, i as f32 * TICK_DT));
+    }
+    start.elapsed()
+}
+
+/// Includes the time it takes to bake the grid.
+fn sample_baked(map: &Map) -> Duration {
+    let start = Instant::now();
+    let half = ARENA / 2.0;
+    let grid = FieldGrid::bake(&map.field, -half, half, BakeSettings::default().cell, 0.0);
+    for pos in positions().take(SAMPLES) {
+        black_box(grid.sample(pos));
+    }
+    start.elapsed()
+}
+
+/// Steps a world with `projectiles` of them in flight the whole time, firing
+/// new ones from random places as the old ones leave the arena.
+fn run_ticks(map: Map, projectiles: usize) -> Duration {
+    let mut world = World::new(map, 0);
+    let mut positions = positions();
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        while world.projectiles.len() < projectiles {
+            let pos = positions.next().unwrap() + ARENA / 2.0;
+            let vel = Vec2::from_angle(pos.x) * PROJECTILE_INIT_SPEED;
+            world
+                .projectiles
+                .push(Projectile::new(pos, vel, ProjectileKind::Standard));
+        }
+        black_box(world.step(TICK_DT, &Input::default()));
+    }
+    start.elapsed()
+}
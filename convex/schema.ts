@@ -2,19 +2,39 @@ import { zodToConvex } from "convex-helpers/server/zod";
 import { defineSchema, defineTable } from "convex/server";
 import { z } from "zod";
 
+// scores from before there were modes leave `mode` out. they were never
+// verified, so they aren't on any leaderboard
+export const modes = [
+  "time_attack",
+  "survival",
+  "pacifist",
+  "target_run",
+] as const;
+
+// every built-in map, as named in its file in maps/ (see BUILTIN_MAPS in
+// src/map.rs)
+export const maps = [
+  "dual vision",
+  "curl valley",
+  "clockback",
+  "whirlpool",
+  "open sea",
+  "rapids",
+  "menagerie",
+  "gauntlet",
+] as const;
+
 export const Score = z.object({
   name: z.string().max(2),
   score: z.number(),
-  map: z.union([
-    z.literal("dual vision"),
-    z.literal("clockback"),
-    z.literal("curl valley"),
-  ]),
+  map: z.enum(maps),
+  mode: z.enum(modes).optional(),
 });
 
 export default defineSchema({
-  scores: defineTable(zodToConvex(Score)).index("by_map_score", [
+  scores: defineTable(zodToConvex(Score)).index("by_map_mode_score", [
     "map",
+    "mode",
     "score",
   ]),
 });
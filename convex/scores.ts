@@ -1,4 +1,3 @@
-import { Infer } from "convex/values";
 import { Doc } from "./_generated/dataModel";
 import {
   httpAction,
@@ -6,39 +5,81 @@ import {
   internalQuery,
 } from "./_generated/server";
 import { internal } from "./_generated/api";
-import schema, { Score } from "./schema";
+import schema, { maps, modes, Score } from "./schema";
+
+type Mode = (typeof modes)[number];
+
+// the same names the game gives its leaderboards
+const modeNames: Record<Mode, string> = {
+  time_attack: "time attack",
+  survival: "survival",
+  pacifist: "pacifist",
+  target_run: "target run",
+};
+
+function leaderboard(map: string, mode: Mode) {
+  return `${map} (${modeNames[mode]})`;
+}
 
 export const _newScore = internalMutation({
   args: schema.tables.scores.validator,
-  handler: async (ctx, args) => {
-    await ctx.db.insert("scores", args);
+  handler: async (ctx, { mode, ...score }) => {
+    // every verified score has a mode, so it's never mixed in with the old
+    // unverified ones
+    await ctx.db.insert("scores", { ...score, mode: mode ?? "time_attack" });
   },
 });
 
 export const _getTopScores = internalQuery({
   args: {},
   handler: async (ctx) => {
-    const topScores: Partial<
-      Record<
-        Infer<typeof schema.tables.scores.validator.fields.map>,
-        Doc<"scores">[]
-      >
-    > = {};
-    for (const { value: map } of schema.tables.scores.validator.fields.map
-      .members) {
-      topScores[map] = await ctx.db
-        .query("scores")
-        .withIndex("by_map_score")
-        .filter((q) => q.eq(q.field("map"), map))
-        .order("desc")
-        .take(5);
+    const topScores: Record<string, Doc<"scores">[]> = {};
+    for (const map of maps) {
+      for (const mode of modes) {
+        // target runs are timed, so the lowest scores are the best
+        topScores[leaderboard(map, mode)] = await ctx.db
+          .query("scores")
+          .withIndex("by_map_mode_score", (q) =>
+            q.eq("map", map).eq("mode", mode),
+          )
+          .order(mode === "target_run" ? "asc" : "desc")
+          .take(5);
+      }
     }
     return topScores;
   },
 });
 
 export const newScore = httpAction(async (ctx, request) => {
-  const score = Score.parse(await request.json());
+  const submission = await request.json();
+  const parsed = Score.safeParse(submission);
+  if (!parsed.success) {
+    return new Response(JSON.stringify({ error: parsed.error.message }), {
+      status: 400,
+    });
+  }
+  const score = parsed.data;
+
+  // re-simulate the attached replay with `flowfield-verify --serve` and only
+  // keep the score if it really came out of the run
+  const verifierUrl = process.env.VERIFIER_URL;
+  if (!verifierUrl) {
+    return new Response(
+      JSON.stringify({
+        error: "scores can't be verified: VERIFIER_URL isn't set on the backend",
+      }),
+      { status: 500 },
+    );
+  }
+  const verification = await fetch(`${verifierUrl}/verify`, {
+    method: "POST",
+    body: JSON.stringify(submission),
+  });
+  if (!verification.ok) {
+    return new Response(await verification.text(), {
+      status: 400,
+    });
+  }
 
   await ctx.runMutation(internal.scores._newScore, score);
 
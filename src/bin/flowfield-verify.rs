@@ -0,0 +1,168 @@
+//! Re-simulates submitted replays to check that their scores are real.
+//!
+//! `flowfield-verify <submission.json>` checks a single submission and exits
+//! with an error if it's rejected.
+//!
+//! `flowfield-verify --serve <addr>` runs an HTTP server with:
+//! - `POST /verify`, which the backend calls before accepting a score
+//! - `POST /newScore` and `GET /topScores`, an in-memory stand-in for the
+//!   backend so the game can be pointed at it with `FLOWFIELD_SERVER`
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use serde_json::json;
+
+use flowfield::map::{builtin_maps, Map};
+use flowfield::mode::GameMode;
+use flowfield::verify::{verify, Score, Submission};
+
+const TOP_SCORES: usize = 5;
+/// Biggest request body accepted, comfortably more than the replay of the
+/// longest run there can be.
+const MAX_BODY: usize = 16 << 20;
+/// Most bytes of request line and headers read before giving up on them.
+const MAX_HEADERS: usize = 64 << 10;
+/// How long a client gets to send each part of its request, since requests
+/// are handled one at a time and a stalled one would hold up the rest.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [flag, addr] if flag == "--serve" => serve(addr),
+        [path] => {
+            let submission: Submission = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+            let score = verify(&submission, &builtin_maps())?;
+            println!(
+                "ok: {} scored {} on {}",
+                submission.name,
+                score,
+                submission.mode.leaderboard(&submission.map)
+            );
+            Ok(())
+        }
+        _ => bail!("usage: flowfield-verify <submission.json> | --serve <addr>"),
+    }
+}
+
+fn serve(addr: &str) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("listening on {}", listener.local_addr()?);
+    let maps = builtin_maps();
+    let mut scores: Vec<Score> = vec![];
+    for stream in listener.incoming() {
+        if let Err(err) = stream
+            .map_err(anyhow::Error::from)
+            .and_then(|stream| handle(stream, &maps, &mut scores))
+        {
+            eprintln!("error: {}", err);
+        }
+    }
+    Ok(())
+}
+
+fn handle(mut stream: TcpStream, maps: &[Map], scores: &mut Vec<Score>) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new((&stream).take(MAX_HEADERS as u64));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = parts
+        .next()
+        .zip(parts.next())
+        .ok_or_else(|| anyhow!("bad request line {:?}", request_line))?;
+
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse()?;
+            }
+        }
+    }
+    reader.get_mut().set_limit(MAX_BODY as u64);
+    let (status, response) = if content_length > MAX_BODY {
+        (
+            "413 Payload Too Large",
+            json!({ "error": format!("request bodies can be at most {} bytes", MAX_BODY) })
+                .to_string(),
+        )
+    } else {
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        route(method, path, &body, maps, scores)
+    };
+    println!("{} {} -> {}", method, path, status);
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        response.len(),
+        response
+    )?;
+    Ok(())
+}
+
+fn route(
+    method: &str,
+    path: &str,
+    body: &[u8],
+    maps: &[Map],
+    scores: &mut Vec<Score>,
+) -> (&'static str, String) {
+    match (method, path) {
+        ("POST", "/verify") => match check(body, maps) {
+            Ok(submission) => ("200 OK", json!({ "score": submission.score }).to_string()),
+            Err(err) => (
+                "400 Bad Request",
+                json!({ "error": err.to_string() }).to_string(),
+            ),
+        },
+        ("POST", "/newScore") => match check(body, maps) {
+            Ok(submission) => {
+                scores.push(submission.score());
+                ("200 OK", String::new())
+            }
+            Err(err) => (
+                "400 Bad Request",
+                json!({ "error": err.to_string() }).to_string(),
+            ),
+        },
+        ("GET", "/topScores") => ("200 OK", top_scores(maps, scores).to_string()),
+        _ => ("404 Not Found", String::new()),
+    }
+}
+
+fn check(body: &[u8], maps: &[Map]) -> anyhow::Result<Submission> {
+    let submission: Submission = serde_json::from_slice(body)?;
+    verify(&submission, maps)?;
+    Ok(submission)
+}
+
+/// The best scores on each map in each mode, keyed by leaderboard.
+fn top_scores(maps: &[Map], scores: &[Score]) -> serde_json::Value {
+    let mut top_scores = HashMap::new();
+    for map in maps {
+        for mode in GameMode::ALL {
+            let mut map_scores: Vec<&Score> = scores
+                .iter()
+                .filter(|score| score.map == map.name && score.mode == mode)
+                .collect();
+            map_scores.sort_by_key(|score| mode.rank(score.score));
+            map_scores.truncate(TOP_SCORES);
+            top_scores.insert(mode.leaderboard(&map.name), map_scores);
+        }
+    }
+    json!(top_scores)
+}
@@ -0,0 +1,331 @@
+use macroquad::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::physics::Physics;
+use crate::sim::{Body, ENEMY_INIT_SPEED, ENEMY_RADIUS};
+
+/// Points for shooting an enemy, unless its type says otherwise.
+pub const ENEMY_SCORE: i32 = 100;
+/// How hard field riders correct their course to match the flow.
+pub const RIDER_GRIP: f32 = 5.0;
+/// How much smaller each piece of a splitter is than what it split from.
+pub const SPLIT_SHRINK: f32 = 0.6;
+/// The most pieces a splitter can break into, and the most times they can
+/// break up again, which between them decide how many enemies shooting one
+/// can make.
+pub const MAX_SPLIT_PIECES: u32 = 8;
+pub const MAX_SPLIT_GENERATIONS: u32 = 3;
+
+/// What an enemy does once it's launched.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Behaviour {
+    /// goes wherever the field takes it
+    Drifter,
+    /// chases the player, pushing back against the flow
+    Seeker { accel: f32 },
+    /// breaks into `pieces` smaller enemies when shot, which split again
+    /// until they've done it `generations` times
+    Splitter { pieces: u32, generations: u32 },
+    /// takes `hp` hits to kill, and each hit stops the projectile
+    Tank { hp: u32 },
+    /// lunges at the player at `dash` pixels a second every `period`
+    /// seconds
+    Dasher { dash: f32, period: f32 },
+    /// steers to go along the field's streamlines at `cruise` pixels a
+    /// second
+    Rider { cruise: f32 },
+}
+
+/// A kind of enemy a map can spawn.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EnemyType {
+    #[serde(flatten)]
+    pub behaviour: Behaviour,
+    #[serde(default = "default_radius")]
+    pub radius: f32,
+    /// the map's enemy colour if left out
+    #[serde(
+        default,
+        with = "crate::map::hex::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub color: Option<u32>,
+    /// points for shooting one
+    #[serde(default = "default_score")]
+    pub score: i32,
+    /// how likely this type is to spawn, relative to the others
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    /// how fast it's launched towards the player
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    #[serde(default = "Physics::enemy")]
+    pub physics: Physics,
+}
+
+/// One of `types`, chosen by their weights from a random `roll`.
+pub fn pick(types: &[EnemyType], roll: u32) -> Option<&EnemyType> {
+    let total: f32 = types.iter().map(|kind| kind.weight.max(0.0)).sum();
+    let mut left = roll as f32 / u32::MAX as f32 * total;
+    types
+        .iter()
+        .find(|kind| {
+            left -= kind.weight.max(0.0);
+            left < 0.0
+        })
+        // the very top rolls use up every weight, and go to the last type
+        // that could come up at all
+        .or_else(|| types.iter().rev().find(|kind| kind.weight > 0.0))
+        .or(types.last())
+}
+
+fn default_radius() -> f32 {
+    ENEMY_RADIUS
+}
+
+fn default_score() -> i32 {
+    ENEMY_SCORE
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+fn default_speed() -> f32 {
+    ENEMY_INIT_SPEED
+}
+
+#[derive(Clone)]
+pub struct Enemy {
+    pub body: Body,
+    pub behaviour: Behaviour,
+    pub radius: f32,
+    pub color: Option<u32>,
+    pub score: i32,
+    /// hits left before it dies
+    pub hp: u32,
+    /// times left for a splitter to split
+    pub generations: u32,
+    /// seconds until a dasher dashes again
+    pub timer: f32,
+}
+
+impl Enemy {
+    /// One of the plain enemies maps without any enemy types spawn.
+    pub fn new(body: Body, radius: f32) -> Enemy {
+        Enemy {
+            body,
+            behaviour: Behaviour::Drifter,
+            radius,
+            color: None,
+            score: ENEMY_SCORE,
+            hp: 1,
+            generations: 0,
+            timer: 0.0,
+        }
+    }
+
+    pub fn spawn(kind: &EnemyType, pos: Vec2, vel: Vec2) -> Enemy {
+        let (hp, generations, timer) = match kind.behaviour {
+            Behaviour::Tank { hp } => (hp.max(1), 0, 0.0),
+            Behaviour::Splitter { generations, .. } => (1, generations, 0.0),
+            Behaviour::Dasher { period, .. } => (1, 0, period),
+            _ => (1, 0, 0.0),
+        };
+        Enemy {
+            body: Body::new(pos, vel).with_physics(kind.physics),
+            behaviour: kind.behaviour,
+            radius: kind.radius,
+            color: kind.color,
+            score: kind.score,
+            hp,
+            generations,
+            timer,
+        }
+    }
+
+    /// How hard the enemy pushes itself, with the player at `target` and
+    /// the field being `field` where it is. Dashers lunge here too, when
+    /// it's time.
+    pub fn thrust(&mut self, dt: f32, target: Vec2, field: Vec2) -> Vec2 {
+        let to_target = (target - self.body.pos).normalize_or_zero();
+        match self.behaviour {
+            Behaviour::Seeker { accel } => accel * to_target - self.body.physics.coupling * field,
+            Behaviour::Dasher { dash, period } => {
+                self.timer -= dt;
+                if self.timer <= 0.0 {
+                    self.timer += period;
+                    self.body.vel = dash * to_target;
+                }
+                Vec2::ZERO
+            }
+            Behaviour::Rider { cruise } => {
+                RIDER_GRIP * (cruise * field.normalize_or_zero() - self.body.vel)
+            }
+            _ => Vec2::ZERO,
+        }
+    }
+
+    /// Whether shooting it dead breaks it into pieces.
+    pub fn splits(&self) -> bool {
+        let pieces = match self.behaviour {
+            Behaviour::Splitter { pieces, .. } => pieces,
+            _ => 0,
+        };
+        pieces > 0 && self.generations > 0
+    }
+
+    /// What a splitter breaks into when it's shot, fanned out evenly and
+    /// flying apart. Nothing for anything else.
+    pub fn split(&self) -> Vec<Enemy> {
+        let Behaviour::Splitter { pieces, .. } = self.behaviour else {
+            return vec![];
+        };
+        if self.generations == 0 {
+            return vec![];
+        }
+        let speed = self.body.vel.length().max(ENEMY_INIT_SPEED);
+        (0..pieces)
+            .map(|i| {
+                let dir = Vec2::from_angle(std::f32::consts::TAU * i as f32 / pieces as f32)
+                    .rotate(self.body.vel.normalize_or(Vec2::X).perp());
+                let mut piece = self.clone();
+                piece.radius = self.radius * SPLIT_SHRINK;
+                piece.generations = self.generations - 1;
+                piece.hp = 1;
+                piece.body = Body::new(self.body.pos + dir * piece.radius, dir * speed)
+                    .with_physics(self.body.physics);
+                piece
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(behaviour: Behaviour) -> EnemyType {
+        EnemyType {
+            behaviour,
+            radius: 50.0,
+            color: None,
+            score: ENEMY_SCORE,
+            weight: 1.0,
+            speed: ENEMY_INIT_SPEED,
+            physics: Physics::enemy(),
+        }
+    }
+
+    fn spawn(behaviour: Behaviour) -> Enemy {
+        Enemy::spawn(
+            &kind(behaviour),
+            Vec2::new(100.0, 100.0),
+            Vec2::new(0.0, 300.0),
+        )
+    }
+
+    #[test]
+    fn picks_by_weight() {
+        let types: Vec<EnemyType> = [1.0, 3.0, 0.0]
+            .into_iter()
+            .enumerate()
+            .map(|(i, weight)| EnemyType {
+                score: i as i32,
+                weight,
+                ..kind(Behaviour::Drifter)
+            })
+            .collect();
+        let picked = |fraction: f64| {
+            pick(&types, (fraction * u32::MAX as f64) as u32)
+                .unwrap()
+                .score
+        };
+        assert_eq!(picked(0.0), 0);
+        assert_eq!(picked(0.2), 0);
+        assert_eq!(picked(0.3), 1);
+        assert_eq!(picked(0.9), 1);
+        // one with no weight never comes up, even for the highest roll
+        assert_eq!(picked(1.0), 1);
+        assert!(pick(&[], 12345).is_none());
+    }
+
+    #[test]
+    fn splits_until_out_of_generations() {
+        let splitter = spawn(Behaviour::Splitter {
+            pieces: 3,
+            generations: 2,
+        });
+        assert!(splitter.splits());
+        let pieces = splitter.split();
+        assert_eq!(pieces.len(), 3);
+        for piece in &pieces {
+            assert_eq!(piece.generations, 1);
+            assert_eq!(piece.hp, 1);
+            assert_eq!(piece.radius, 50.0 * SPLIT_SHRINK);
+            // flying apart, from where it was shot
+            assert!(piece.body.pos.distance(splitter.body.pos) <= piece.radius + 1e-3);
+            assert!((piece.body.vel.length() - ENEMY_INIT_SPEED).abs() < 1e-2);
+        }
+        let last: Vec<Enemy> = pieces.iter().flat_map(|piece| piece.split()).collect();
+        assert_eq!(last.len(), 9);
+        for piece in &last {
+            assert_eq!(piece.generations, 0);
+            assert!(!piece.splits());
+            assert!(piece.split().is_empty());
+        }
+
+        let plain = spawn(Behaviour::Drifter);
+        assert!(!plain.splits());
+        assert!(plain.split().is_empty());
+        let no_pieces = spawn(Behaviour::Splitter {
+            pieces: 0,
+            generations: 2,
+        });
+        assert!(!no_pieces.splits());
+        assert!(no_pieces.split().is_empty());
+    }
+
+    #[test]
+    fn tanks_take_several_hits() {
+        assert_eq!(spawn(Behaviour::Tank { hp: 3 }).hp, 3);
+        // and at least one
+        assert_eq!(spawn(Behaviour::Tank { hp: 0 }).hp, 1);
+        assert_eq!(spawn(Behaviour::Drifter).hp, 1);
+    }
+
+    #[test]
+    fn thrust() {
+        let field = Vec2::new(200.0, 0.0);
+        let target = Vec2::new(100.0, 0.0);
+
+        let mut drifter = spawn(Behaviour::Drifter);
+        assert_eq!(drifter.thrust(0.1, target, field), Vec2::ZERO);
+
+        // seekers head for the player and cancel out the field
+        let mut seeker = spawn(Behaviour::Seeker { accel: 300.0 });
+        let coupling = seeker.body.physics.coupling;
+        assert_eq!(
+            seeker.thrust(0.1, target, field),
+            Vec2::new(0.0, -300.0) - coupling * field
+        );
+
+        let mut dasher = spawn(Behaviour::Dasher {
+            dash: 900.0,
+            period: 1.0,
+        });
+        assert_eq!(dasher.thrust(0.6, target, field), Vec2::ZERO);
+        assert_eq!(dasher.body.vel, Vec2::new(0.0, 300.0));
+        dasher.thrust(0.6, target, field);
+        assert_eq!(dasher.body.vel, Vec2::new(0.0, -900.0));
+        assert!((dasher.timer - 0.8).abs() < 1e-5);
+
+        // riders steer toward going with the flow at their cruising speed
+        let mut rider = spawn(Behaviour::Rider { cruise: 100.0 });
+        assert_eq!(
+            rider.thrust(0.1, target, field),
+            RIDER_GRIP * Vec2::new(100.0, -300.0)
+        );
+    }
+}
@@ -0,0 +1,780 @@
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use anyhow::{anyhow, bail};
+use macroquad::math::Vec2;
+
+/// Fields typed in by the player, either as a vector `F(x, y) = (.., ..)` or
+/// as a complex function `f(z) = ..` whose real and imaginary parts give the
+/// x and y of the field. Everything is evaluated in complex arithmetic, with
+/// `x` and `y` the real and imaginary parts of `z`, and `t` the number of
+/// seconds since the run started.
+///
+/// Expressions are compiled once into a small stack program, so sampling
+/// them every tick is cheap.
+#[derive(Clone, Debug)]
+pub struct Program {
+    ops: Vec<Op>,
+    /// two results for `(.., ..)`, one for a complex function
+    outputs: usize,
+}
+
+/// How deep the evaluation stack can get; deeper expressions are rejected.
+const MAX_STACK: usize = 32;
+/// How many brackets, function calls, signs and powers can be nested inside
+/// each other, so the parser can't run out of stack on a long chain of them.
+const MAX_NESTING: usize = 64;
+/// Longest expression accepted, in tokens, which also keeps how deep a long
+/// chain of sums or products gets within bounds.
+const MAX_TOKENS: usize = 500;
+
+impl Program {
+    pub fn compile(source: &str) -> anyhow::Result<Program> {
+        let tokens = tokenize(source)?;
+        if tokens.len() > MAX_TOKENS {
+            bail!("expression is too long");
+        }
+        let mut parser = Parser {
+            tokens,
+            next: 0,
+            depth: 0,
+        };
+        parser.header()?;
+        let ast = parser.top()?;
+        if let Some(token) = parser.tokens.get(parser.next) {
+            bail!("unexpected {} at {}", token.kind, token.at);
+        }
+        let outputs = match ast {
+            Ast::Tuple(..) => 2,
+            _ => 1,
+        };
+        let mut ops = vec![];
+        emit(&fold(ast), &mut ops);
+        let program = Program { ops, outputs };
+        if program.max_depth() > MAX_STACK {
+            bail!("expression is too deeply nested");
+        }
+        Ok(program)
+    }
+
+    fn max_depth(&self) -> usize {
+        let mut depth: usize = 0;
+        let mut max = 0;
+        for op in &self.ops {
+            match op {
+                Op::Const(_) | Op::X | Op::Y | Op::Z | Op::T => depth += 1,
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow => depth -= 1,
+                Op::Neg | Op::PowI(_) | Op::Call(_) => {}
+            }
+            max = max.max(depth);
+        }
+        max
+    }
+
+    /// Whether the expression mentions `t`.
+    pub fn uses_time(&self) -> bool {
+        self.ops.iter().any(|op| matches!(op, Op::T))
+    }
+
+    pub fn eval(&self, pos: Vec2, time: f32) -> Vec2 {
+        let z = C::new(pos.x as f64, pos.y as f64);
+        let mut stack = [C::ZERO; MAX_STACK];
+        let mut top = 0;
+        for op in &self.ops {
+            match *op {
+                Op::Const(c) => {
+                    stack[top] = c;
+                    top += 1;
+                }
+                Op::X => {
+                    stack[top] = C::new(z.re, 0.0);
+                    top += 1;
+                }
+                Op::Y => {
+                    stack[top] = C::new(z.im, 0.0);
+                    top += 1;
+                }
+                Op::Z => {
+                    stack[top] = z;
+                    top += 1;
+                }
+                Op::T => {
+                    stack[top] = C::new(time as f64, 0.0);
+                    top += 1;
+                }
+                Op::Neg => stack[top - 1] = -stack[top - 1],
+                Op::PowI(n) => stack[top - 1] = stack[top - 1].powi(n),
+                Op::Call(func) => stack[top - 1] = func.apply(stack[top - 1]),
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow => {
+                    top -= 1;
+                    let (a, b) = (stack[top - 1], stack[top]);
+                    stack[top - 1] = match op {
+                        Op::Add => a + b,
+                        Op::Sub => a - b,
+                        Op::Mul => a * b,
+                        Op::Div => a / b,
+                        _ => a.pow(b),
+                    };
+                }
+            }
+        }
+        if self.outputs == 2 {
+            Vec2::new(stack[0].re as f32, stack[1].re as f32)
+        } else {
+            Vec2::new(stack[0].re as f32, stack[0].im as f32)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct C {
+    re: f64,
+    im: f64,
+}
+
+impl C {
+    const ZERO: C = C::new(0.0, 0.0);
+    const ONE: C = C::new(1.0, 0.0);
+
+    const fn new(re: f64, im: f64) -> C {
+        C { re, im }
+    }
+
+    fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    fn exp(self) -> C {
+        let r = self.re.exp();
+        C::new(r * self.im.cos(), r * self.im.sin())
+    }
+
+    fn ln(self) -> C {
+        C::new(self.abs().ln(), self.arg())
+    }
+
+    fn sqrt(self) -> C {
+        let (r, theta) = (self.abs().sqrt(), self.arg() / 2.0);
+        C::new(r * theta.cos(), r * theta.sin())
+    }
+
+    fn sin(self) -> C {
+        C::new(
+            self.re.sin() * self.im.cosh(),
+            self.re.cos() * self.im.sinh(),
+        )
+    }
+
+    fn cos(self) -> C {
+        C::new(
+            self.re.cos() * self.im.cosh(),
+            -self.re.sin() * self.im.sinh(),
+        )
+    }
+
+    fn sinh(self) -> C {
+        C::new(
+            self.re.sinh() * self.im.cos(),
+            self.re.cosh() * self.im.sin(),
+        )
+    }
+
+    fn cosh(self) -> C {
+        C::new(
+            self.re.cosh() * self.im.cos(),
+            self.re.sinh() * self.im.sin(),
+        )
+    }
+
+    fn powi(self, n: i32) -> C {
+        let mut result = C::ONE;
+        let mut base = self;
+        let mut e = n.unsigned_abs();
+        while e > 0 {
+            if e & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            e >>= 1;
+        }
+        if n < 0 {
+            C::ONE / result
+        } else {
+            result
+        }
+    }
+
+    fn pow(self, w: C) -> C {
+        if self == C::ZERO {
+            return if w.re > 0.0 {
+                C::ZERO
+            } else {
+                C::ONE / C::ZERO
+            };
+        }
+        (w * self.ln()).exp()
+    }
+}
+
+impl Add for C {
+    type Output = C;
+    fn add(self, b: C) -> C {
+        C::new(self.re + b.re, self.im + b.im)
+    }
+}
+
+impl Sub for C {
+    type Output = C;
+    fn sub(self, b: C) -> C {
+        C::new(self.re - b.re, self.im - b.im)
+    }
+}
+
+impl Mul for C {
+    type Output = C;
+    fn mul(self, b: C) -> C {
+        C::new(
+            self.re * b.re - self.im * b.im,
+            self.re * b.im + self.im * b.re,
+        )
+    }
+}
+
+impl Div for C {
+    type Output = C;
+    fn div(self, b: C) -> C {
+        let d = b.re * b.re + b.im * b.im;
+        C::new(
+            (self.re * b.re + self.im * b.im) / d,
+            (self.im * b.re - self.re * b.im) / d,
+        )
+    }
+}
+
+impl Neg for C {
+    type Output = C;
+    fn neg(self) -> C {
+        C::new(-self.re, -self.im)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Func {
+    Sin,
+    Cos,
+    Tan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    Ln,
+    Sqrt,
+    Abs,
+    Re,
+    Im,
+    Conj,
+    Arg,
+}
+
+impl Func {
+    fn from_name(name: &str) -> Option<Func> {
+        Some(match name {
+            "sin" => Func::Sin,
+            "cos" => Func::Cos,
+            "tan" => Func::Tan,
+            "sinh" => Func::Sinh,
+            "cosh" => Func::Cosh,
+            "tanh" => Func::Tanh,
+            "exp" => Func::Exp,
+            "ln" | "log" => Func::Ln,
+            "sqrt" => Func::Sqrt,
+            "abs" => Func::Abs,
+            "re" => Func::Re,
+            "im" => Func::Im,
+            "conj" => Func::Conj,
+            "arg" => Func::Arg,
+            _ => return None,
+        })
+    }
+
+    fn apply(self, z: C) -> C {
+        match self {
+            Func::Sin => z.sin(),
+            Func::Cos => z.cos(),
+            Func::Tan => z.sin() / z.cos(),
+            Func::Sinh => z.sinh(),
+            Func::Cosh => z.cosh(),
+            Func::Tanh => z.sinh() / z.cosh(),
+            Func::Exp => z.exp(),
+            Func::Ln => z.ln(),
+            Func::Sqrt => z.sqrt(),
+            Func::Abs => C::new(z.abs(), 0.0),
+            Func::Re => C::new(z.re, 0.0),
+            Func::Im => C::new(z.im, 0.0),
+            Func::Conj => C::new(z.re, -z.im),
+            Func::Arg => C::new(z.arg(), 0.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Const(C),
+    X,
+    Y,
+    Z,
+    T,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    /// `^` with a constant whole-number exponent
+    PowI(i32),
+    Neg,
+    Call(Func),
+}
+
+#[derive(Clone, Debug)]
+enum Ast {
+    Const(C),
+    X,
+    Y,
+    Z,
+    T,
+    Neg(Box<Ast>),
+    Binary(char, Box<Ast>, Box<Ast>),
+    Call(Func, Box<Ast>),
+    Tuple(Box<Ast>, Box<Ast>),
+}
+
+/// Folds constant subexpressions, so that `z^2` and `z^-1` can use `PowI`
+/// and `x - 4` doesn't negate 4 on every sample.
+fn fold(ast: Ast) -> Ast {
+    match ast {
+        Ast::Neg(a) => match fold(*a) {
+            Ast::Const(c) => Ast::Const(-c),
+            a => Ast::Neg(Box::new(a)),
+        },
+        Ast::Binary(op, a, b) => match (fold(*a), fold(*b)) {
+            (Ast::Const(a), Ast::Const(b)) => Ast::Const(match op {
+                '+' => a + b,
+                '-' => a - b,
+                '*' => a * b,
+                '/' => a / b,
+                _ => a.pow(b),
+            }),
+            (a, b) => Ast::Binary(op, Box::new(a), Box::new(b)),
+        },
+        Ast::Call(func, a) => match fold(*a) {
+            Ast::Const(c) => Ast::Const(func.apply(c)),
+            a => Ast::Call(func, Box::new(a)),
+        },
+        Ast::Tuple(a, b) => Ast::Tuple(Box::new(fold(*a)), Box::new(fold(*b))),
+        ast => ast,
+    }
+}
+
+fn emit(ast: &Ast, ops: &mut Vec<Op>) {
+    match ast {
+        Ast::Const(c) => ops.push(Op::Const(*c)),
+        Ast::X => ops.push(Op::X),
+        Ast::Y => ops.push(Op::Y),
+        Ast::Z => ops.push(Op::Z),
+        Ast::T => ops.push(Op::T),
+        Ast::Neg(a) => {
+            emit(a, ops);
+            ops.push(Op::Neg);
+        }
+        Ast::Binary('^', a, b) => {
+            emit(a, ops);
+            match **b {
+                Ast::Const(c) if c.im == 0.0 && c.re.fract() == 0.0 && c.re.abs() <= 64.0 => {
+                    ops.push(Op::PowI(c.re as i32))
+                }
+                _ => {
+                    emit(b, ops);
+                    ops.push(Op::Pow);
+                }
+            }
+        }
+        Ast::Binary(op, a, b) => {
+            emit(a, ops);
+            emit(b, ops);
+            ops.push(match op {
+                '+' => Op::Add,
+                '-' => Op::Sub,
+                '*' => Op::Mul,
+                '/' => Op::Div,
+                _ => Op::Pow,
+            });
+        }
+        Ast::Call(func, a) => {
+            emit(a, ops);
+            ops.push(Op::Call(*func));
+        }
+        Ast::Tuple(a, b) => {
+            emit(a, ops);
+            emit(b, ops);
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Num(f64),
+    Ident(String),
+    Sym(char),
+}
+
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TokenKind::Num(n) => write!(f, "{}", n),
+            TokenKind::Ident(name) => write!(f, "{:?}", name),
+            TokenKind::Sym(c) => write!(f, "'{}'", c),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    /// character offset into the source, for error messages
+    at: usize,
+}
+
+fn tokenize(source: &str) -> anyhow::Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let kind = if c.is_ascii_digit() || c == '.' {
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            TokenKind::Num(
+                text.parse()
+                    .map_err(|_| anyhow!("bad number {:?} at {}", text, start))?,
+            )
+        } else if c.is_alphabetic() {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            TokenKind::Ident(chars[start..i].iter().collect())
+        } else if "+-*/^(),=".contains(c) {
+            i += 1;
+            TokenKind::Sym(c)
+        } else {
+            bail!("unexpected '{}' at {}", c, start);
+        };
+        tokens.push(Token { kind, at: start });
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    /// how many levels deep the parser is
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.next).map(|token| &token.kind)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(&TokenKind::Sym(c)) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> anyhow::Result<()> {
+        if self.eat(c) {
+            return Ok(());
+        }
+        match self.tokens.get(self.next) {
+            Some(token) => bail!("expected '{}' but found {} at {}", c, token.kind, token.at),
+            None => bail!("expected '{}' but the expression ended", c),
+        }
+    }
+
+    /// Parses something nested inside what's being parsed, or fails if
+    /// that's nested too deeply.
+    fn nested(&mut self, parse: fn(&mut Parser) -> anyhow::Result<Ast>) -> anyhow::Result<Ast> {
+        if self.depth >= MAX_NESTING {
+            bail!("expression is too deeply nested");
+        }
+        self.depth += 1;
+        let ast = parse(self);
+        self.depth -= 1;
+        ast
+    }
+
+    /// Skips an optional `f(z) =`, `F(x, y) =` or `f(z, t) =` in front of the
+    /// expression.
+    fn header(&mut self) -> anyhow::Result<()> {
+        if !self
+            .tokens
+            .iter()
+            .any(|token| token.kind == TokenKind::Sym('='))
+        {
+            return Ok(());
+        }
+        match self.peek() {
+            Some(TokenKind::Ident(_)) => self.next += 1,
+            _ => bail!("expected a name like f(z) before '='"),
+        }
+        self.expect('(')?;
+        loop {
+            match self.peek() {
+                Some(TokenKind::Ident(name)) if ["x", "y", "z", "t"].contains(&name.as_str()) => {
+                    self.next += 1
+                }
+                _ => bail!("parameters can only be x, y, z or t"),
+            }
+            if !self.eat(',') {
+                break;
+            }
+        }
+        self.expect(')')?;
+        self.expect('=')
+    }
+
+    /// An expression, or a pair of them for a vector.
+    fn top(&mut self) -> anyhow::Result<Ast> {
+        let start = self.next;
+        if self.eat('(') {
+            let a = self.expr()?;
+            if self.eat(',') {
+                let b = self.expr()?;
+                self.expect(')')?;
+                if self.next == self.tokens.len() {
+                    return Ok(Ast::Tuple(Box::new(a), Box::new(b)));
+                }
+                bail!("a vector (.., ..) has to be the whole expression");
+            }
+        }
+        self.next = start;
+        self.expr()
+    }
+
+    fn expr(&mut self) -> anyhow::Result<Ast> {
+        let mut ast = self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                '+'
+            } else if self.eat('-') {
+                '-'
+            } else {
+                return Ok(ast);
+            };
+            ast = Ast::Binary(op, Box::new(ast), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> anyhow::Result<Ast> {
+        let mut ast = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                '*'
+            } else if self.eat('/') {
+                '/'
+            } else {
+                return Ok(ast);
+            };
+            ast = Ast::Binary(op, Box::new(ast), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> anyhow::Result<Ast> {
+        if self.eat('-') {
+            return Ok(Ast::Neg(Box::new(self.nested(Parser::unary)?)));
+        }
+        if self.eat('+') {
+            return self.nested(Parser::unary);
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> anyhow::Result<Ast> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            // right associative, and binds tighter than a leading minus:
+            // -z^2 is -(z^2), z^-1 is z^(-1)
+            let exponent = self.nested(Parser::unary)?;
+            return Ok(Ast::Binary('^', Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> anyhow::Result<Ast> {
+        let Some(token) = self.tokens.get(self.next).cloned() else {
+            bail!("the expression ended too soon");
+        };
+        self.next += 1;
+        match token.kind {
+            TokenKind::Num(n) => Ok(Ast::Const(C::new(n, 0.0))),
+            TokenKind::Sym('(') => {
+                let ast = self.nested(Parser::expr)?;
+                self.expect(')')?;
+                Ok(ast)
+            }
+            TokenKind::Ident(name) => match name.as_str() {
+                "x" => Ok(Ast::X),
+                "y" => Ok(Ast::Y),
+                "z" => Ok(Ast::Z),
+                "t" => Ok(Ast::T),
+                "i" => Ok(Ast::Const(C::new(0.0, 1.0))),
+                "pi" => Ok(Ast::Const(C::new(std::f64::consts::PI, 0.0))),
+                "e" => Ok(Ast::Const(C::new(std::f64::consts::E, 0.0))),
+                _ => {
+                    let func = Func::from_name(&name)
+                        .ok_or_else(|| anyhow!("unknown name {:?} at {}", name, token.at))?;
+                    self.expect('(')?;
+                    let arg = self.nested(Parser::expr)?;
+                    self.expect(')')?;
+                    Ok(Ast::Call(func, Box::new(arg)))
+                }
+            },
+            kind => bail!("unexpected {} at {}", kind, token.at),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::{ExpressionField, VectorField};
+
+    fn eval(source: &str, x: f32, y: f32) -> Vec2 {
+        Program::compile(source)
+            .unwrap_or_else(|err| panic!("{:?} didn't compile: {}", source, err))
+            .eval(Vec2::new(x, y), 0.0)
+    }
+
+    fn assert_eval(source: &str, x: f32, y: f32, expected: Vec2) {
+        let result = eval(source, x, y);
+        assert!(
+            result.distance(expected) < 1e-4,
+            "{:?} at ({}, {}) gave {} instead of {}",
+            source,
+            x,
+            y,
+            result,
+            expected
+        );
+    }
+
+    fn compile_error(source: &str) -> String {
+        match Program::compile(source) {
+            Ok(_) => panic!("{:?} compiled", source),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eval("1 + 2 * 3", 0.0, 0.0, Vec2::new(7.0, 0.0));
+        assert_eval("(1 + 2) * 3", 0.0, 0.0, Vec2::new(9.0, 0.0));
+        assert_eval("2 * 3 ^ 2", 0.0, 0.0, Vec2::new(18.0, 0.0));
+        assert_eval("1 + x * y", 2.0, 3.0, Vec2::new(7.0, 0.0));
+    }
+
+    #[test]
+    fn associativity() {
+        assert_eval("8 - 3 - 2", 0.0, 0.0, Vec2::new(3.0, 0.0));
+        assert_eval("16 / 4 / 2", 0.0, 0.0, Vec2::new(2.0, 0.0));
+        // powers go the other way
+        assert_eval("2 ^ 3 ^ 2", 0.0, 0.0, Vec2::new(512.0, 0.0));
+        assert_eval("x ^ y ^ 2", 2.0, 3.0, Vec2::new(512.0, 0.0));
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eval("-2 ^ 2", 0.0, 0.0, Vec2::new(-4.0, 0.0));
+        assert_eval("2 ^ -1", 0.0, 0.0, Vec2::new(0.5, 0.0));
+        assert_eval("--3", 0.0, 0.0, Vec2::new(3.0, 0.0));
+        assert_eval("x - -y", 1.0, 2.0, Vec2::new(3.0, 0.0));
+        assert_eval("-z", 1.0, 2.0, Vec2::new(-1.0, -2.0));
+        assert_eval("+x", 1.0, 2.0, Vec2::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn complex_and_vector_fields() {
+        assert_eval("f(z) = z^2", 1.0, 1.0, Vec2::new(0.0, 2.0));
+        assert_eval("z * i", 1.0, 2.0, Vec2::new(-2.0, 1.0));
+        assert_eval("F(x, y) = (y, -x)", 3.0, 4.0, Vec2::new(4.0, -3.0));
+        assert_eval("(x + 1, y)", 3.0, 4.0, Vec2::new(4.0, 4.0));
+        assert_eval("re(z) + im(z)", 3.0, 4.0, Vec2::new(7.0, 0.0));
+    }
+
+    #[test]
+    fn time() {
+        let program = Program::compile("f(z, t) = z * t").unwrap();
+        assert!(program.uses_time());
+        assert_eq!(program.eval(Vec2::new(1.0, 2.0), 3.0), Vec2::new(3.0, 6.0));
+        assert!(!Program::compile("z").unwrap().uses_time());
+    }
+
+    #[test]
+    fn function_arity() {
+        assert!(compile_error("sin(z, z)").contains("expected ')'"));
+        assert!(compile_error("sin()").contains("unexpected ')'"));
+        assert!(compile_error("sin z").contains("expected '('"));
+        assert!(compile_error("cos(").contains("ended too soon"));
+    }
+
+    #[test]
+    fn unknown_names() {
+        assert!(compile_error("foo(z)").contains("unknown name \"foo\""));
+        assert!(compile_error("w + 1").contains("unknown name \"w\""));
+        assert!(compile_error("g(w) = w").contains("parameters can only be"));
+        assert!(compile_error("z $ 2").contains("unexpected '$'"));
+    }
+
+    #[test]
+    fn nesting() {
+        let nested =
+            |open: &str, close: &str, n: usize| format!("{}z{}", open.repeat(n), close.repeat(n));
+        assert_eval(&nested("(", ")", 40), 1.0, 2.0, Vec2::new(1.0, 2.0));
+        assert_eval(&nested("sin(", ")", 10), 0.0, 0.0, Vec2::ZERO);
+        for source in [
+            nested("(", ")", 100),
+            nested("-", "", 100),
+            nested("sin(", ")", 100),
+            nested("z^", "", 100),
+        ] {
+            assert!(compile_error(&source).contains("too deeply nested"));
+        }
+        // far too long to parse recursively, or to fold and emit
+        assert!(compile_error(&nested("-", "", 100_000)).contains("too long"));
+        assert!(compile_error(&["z"; 100_000].join(" + ")).contains("too long"));
+    }
+
+    #[test]
+    fn non_finite_fields() {
+        assert!(ExpressionField::new("1 / (x - x)", 100.0, 100.0).is_err());
+        assert!(ExpressionField::new("ln(0 * z)", 100.0, 100.0).is_err());
+        assert!(ExpressionField::new("exp(exp(x))", 100.0, 100.0).is_err());
+        // only blows up right in the middle, where it's taken to be zero
+        let field = ExpressionField::new("1 / z", 100.0, 100.0).unwrap();
+        assert_eq!(field.force(Vec2::ZERO, 0.0), Vec2::ZERO);
+        assert!(field.force(Vec2::new(100.0, 0.0), 0.0).is_finite());
+    }
+}
@@ -0,0 +1,628 @@
+use std::f32::consts::TAU;
+
+use anyhow::bail;
+use macroquad::math::Vec2;
+use noise::NoiseFn;
+use noise::OpenSimplex;
+use serde::{Deserialize, Serialize};
+
+use crate::expr::Program;
+
+/// How long an arrow is drawn per unit of force, unless the field knows
+/// better.
+const DEFAULT_ARROW_SCALE: f32 = 0.01;
+
+/// Anything that pushes bodies around. `pos` is relative to the centre of the
+/// arena and `time` is the number of seconds since the run started.
+pub trait VectorField {
+    fn force(&self, pos: Vec2, time: f32) -> Vec2;
+
+    /// what kind of field this is, for showing on the home screen
+    fn name(&self) -> &'static str;
+
+    /// how long to draw an arrow for each unit of force
+    fn arrow_scale(&self) -> f32 {
+        DEFAULT_ARROW_SCALE
+    }
+
+    /// whether the force anywhere changes over time
+    fn is_animated(&self) -> bool {
+        false
+    }
+}
+
+/// A vector field as described in a map file. The combinators (`sum`,
+/// `scale`, `rotate`, `translate` and `blend`) take other fields, so new ones
+/// can be built out of the existing kinds without any code.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Field {
+    Square(Square),
+    CurlNoise(CurlNoise),
+    Circular(Circular),
+    Vortex(Vortex),
+    Pulse(Pulse),
+    Expression(ExpressionField),
+    Sum(Sum),
+    Scale(Scale),
+    Rotate(Rotate),
+    Translate(Translate),
+    Blend(Blend),
+}
+
+impl Field {
+    fn inner(&self) -> &dyn VectorField {
+        match self {
+            Field::Square(field) => field,
+            Field::CurlNoise(field) => field,
+            Field::Circular(field) => field,
+            Field::Vortex(field) => field,
+            Field::Pulse(field) => field,
+            Field::Expression(field) => field,
+            Field::Sum(field) => field,
+            Field::Scale(field) => field,
+            Field::Rotate(field) => field,
+            Field::Translate(field) => field,
+            Field::Blend(field) => field,
+        }
+    }
+
+    /// Fails for settings the field can't be evaluated with, e.g. a period
+    /// of 0, here or in any of the fields it's built from.
+    pub fn check(&self) -> anyhow::Result<()> {
+        match self {
+            Field::Vortex(Vortex { period, .. }) | Field::Pulse(Pulse { period, .. }) => {
+                check_period(*period)
+            }
+            Field::Blend(blend) => {
+                check_period(blend.period)?;
+                blend.from.check()?;
+                blend.to.check()
+            }
+            Field::Sum(sum) => sum.fields.iter().try_for_each(Field::check),
+            Field::Scale(Scale { field, .. })
+            | Field::Rotate(Rotate { field, .. })
+            | Field::Translate(Translate { field, .. }) => field.check(),
+            Field::Square(_) | Field::CurlNoise(_) | Field::Circular(_) | Field::Expression(_) => {
+                Ok(())
+            }
+        }
+    }
+}
+
+fn check_period(period: f32) -> anyhow::Result<()> {
+    if !(period > 0.0 && period.is_finite()) {
+        bail!(
+            "a field's period has to be more than 0 seconds, not {}",
+            period
+        );
+    }
+    Ok(())
+}
+
+impl VectorField for Field {
+    fn force(&self, pos: Vec2, time: f32) -> Vec2 {
+        self.inner().force(pos, time)
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner().name()
+    }
+
+    fn arrow_scale(&self) -> f32 {
+        self.inner().arrow_scale()
+    }
+
+    fn is_animated(&self) -> bool {
+        self.inner().is_animated()
+    }
+}
+
+/// `scale * (z² + c)`, treating positions as complex numbers
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Square {
+    pub scale: f32,
+    pub c: [f32; 2],
+}
+
+impl VectorField for Square {
+    fn force(&self, pos: Vec2, _time: f32) -> Vec2 {
+        get_vector_field_force_square(pos, self.scale, self.c)
+    }
+
+    fn name(&self) -> &'static str {
+        "square"
+    }
+}
+
+fn get_vector_field_force_square(pos: Vec2, scale: f32, c: [f32; 2]) -> Vec2 {
+    let Vec2 { x, y } = pos;
+    scale * Vec2::new(x * x - y * y + c[0], 2.0 * x * y + c[1])
+}
+
+/// Flows along the contours of simplex noise, which drifts `scroll` pixels a
+/// second. The noise generator is built once, when the field is.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "CurlNoiseDef", into = "CurlNoiseDef")]
+pub struct CurlNoise {
+    seed: u32,
+    pub zoom: f32,
+    pub strength: f32,
+    pub scroll: [f32; 2],
+    /// boxed, since the permutation table would make every `Field` huge
+    noise: Box<OpenSimplex>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CurlNoiseDef {
+    seed: u32,
+    zoom: f32,
+    strength: f32,
+    #[serde(default)]
+    scroll: [f32; 2],
+}
+
+impl CurlNoise {
+    pub fn new(seed: u32, zoom: f32, strength: f32, scroll: [f32; 2]) -> CurlNoise {
+        CurlNoise {
+            seed,
+            zoom,
+            strength,
+            scroll,
+            noise: Box::new(OpenSimplex::new(seed)),
+        }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+impl VectorField for CurlNoise {
+    fn force(&self, pos: Vec2, time: f32) -> Vec2 {
+        let pos = pos - time * Vec2::from_array(self.scroll);
+        get_vector_field_force_curl_noise(pos, &self.noise, self.zoom, self.strength)
+    }
+
+    fn name(&self) -> &'static str {
+        "curl noise"
+    }
+
+    fn is_animated(&self) -> bool {
+        self.scroll != [0.0; 2]
+    }
+}
+
+impl PartialEq for CurlNoise {
+    fn eq(&self, other: &CurlNoise) -> bool {
+        self.seed == other.seed
+            && self.zoom == other.zoom
+            && self.strength == other.strength
+            && self.scroll == other.scroll
+    }
+}
+
+impl From<CurlNoiseDef> for CurlNoise {
+    fn from(def: CurlNoiseDef) -> CurlNoise {
+        CurlNoise::new(def.seed, def.zoom, def.strength, def.scroll)
+    }
+}
+
+impl From<CurlNoise> for CurlNoiseDef {
+    fn from(field: CurlNoise) -> CurlNoiseDef {
+        CurlNoiseDef {
+            seed: field.seed,
+            zoom: field.zoom,
+            strength: field.strength,
+            scroll: field.scroll,
+        }
+    }
+}
+
+fn get_vector_field_force_curl_noise(
+    pos: Vec2,
+    noise: &OpenSimplex,
+    zoom: f32,
+    strength: f32,
+) -> Vec2 {
+    const DERIVATIVE_SAMPLE: f64 = 0.001;
+    let Vec2 { x: _x, y: _y } = pos / zoom;
+    let x = _x as f64;
+    let y = _y as f64;
+    let x1 = noise.get([x + DERIVATIVE_SAMPLE, y]);
+    let x2 = noise.get([x - DERIVATIVE_SAMPLE, y]);
+    let y1 = noise.get([x, y + DERIVATIVE_SAMPLE]);
+    let y2 = noise.get([x, y - DERIVATIVE_SAMPLE]);
+    let x_d = x2 - x1;
+    let y_d = y2 - y1;
+    let angle = y_d.atan2(x_d);
+    strength * Vec2::from_angle(angle as f32)
+}
+
+/// Spins around the centre, against the clock on the left and with it on the
+/// right.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Circular {
+    pub strength: f32,
+}
+
+impl VectorField for Circular {
+    fn force(&self, pos: Vec2, _time: f32) -> Vec2 {
+        get_vector_field_force_circular(pos, self.strength)
+    }
+
+    fn name(&self) -> &'static str {
+        "circular"
+    }
+}
+
+fn get_vector_field_force_circular(pos: Vec2, strength: f32) -> Vec2 {
+    let Vec2 { x, y } = pos;
+    let angle = (-y / x).atan();
+    if x < 0.0 {
+        strength * -Vec2::from_angle(-angle).rotate(-Vec2::Y)
+    } else {
+        strength * Vec2::from_angle(-angle).rotate(-Vec2::Y)
+    }
+}
+
+/// Swirls around a centre that goes round the middle of the arena once every
+/// `period` seconds at a distance of `orbit`. Spins like a solid inside
+/// `radius` and falls off outside it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vortex {
+    pub strength: f32,
+    pub radius: f32,
+    #[serde(default)]
+    pub orbit: f32,
+    #[serde(default = "default_period")]
+    pub period: f32,
+}
+
+fn default_period() -> f32 {
+    10.0
+}
+
+impl VectorField for Vortex {
+    fn force(&self, pos: Vec2, time: f32) -> Vec2 {
+        let center = self.orbit * Vec2::from_angle(TAU * time / self.period);
+        get_vector_field_force_vortex(pos - center, self.strength, self.radius)
+    }
+
+    fn name(&self) -> &'static str {
+        "vortex"
+    }
+
+    fn is_animated(&self) -> bool {
+        self.orbit != 0.0
+    }
+}
+
+fn get_vector_field_force_vortex(pos: Vec2, strength: f32, radius: f32) -> Vec2 {
+    let dist = pos.length();
+    let speed = if dist < radius {
+        dist / radius
+    } else {
+        radius / dist
+    };
+    strength * speed * pos.normalize_or_zero().perp()
+}
+
+/// Pushes out from the centre and then pulls back in, once every `period`
+/// seconds.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pulse {
+    pub strength: f32,
+    pub period: f32,
+}
+
+impl VectorField for Pulse {
+    fn force(&self, pos: Vec2, time: f32) -> Vec2 {
+        (self.strength * (TAU * time / self.period).sin()) * pos.normalize_or_zero()
+    }
+
+    fn name(&self) -> &'static str {
+        "pulse"
+    }
+
+    fn is_animated(&self) -> bool {
+        true
+    }
+}
+
+/// Several fields added together.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sum {
+    pub fields: Vec<Field>,
+}
+
+impl VectorField for Sum {
+    fn force(&self, pos: Vec2, time: f32) -> Vec2 {
+        self.fields.iter().map(|field| field.force(pos, time)).sum()
+    }
+
+    fn name(&self) -> &'static str {
+        "sum"
+    }
+
+    fn arrow_scale(&self) -> f32 {
+        self.fields
+            .iter()
+            .map(|field| field.arrow_scale())
+            .reduce(f32::min)
+            .unwrap_or(DEFAULT_ARROW_SCALE)
+    }
+
+    fn is_animated(&self) -> bool {
+        self.fields.iter().any(|field| field.is_animated())
+    }
+}
+
+/// Another field, `factor` times as strong.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Scale {
+    pub factor: f32,
+    pub field: Box<Field>,
+}
+
+impl VectorField for Scale {
+    fn force(&self, pos: Vec2, time: f32) -> Vec2 {
+        self.factor * self.field.force(pos, time)
+    }
+
+    fn name(&self) -> &'static str {
+        self.field.name()
+    }
+
+    fn arrow_scale(&self) -> f32 {
+        if self.factor == 0.0 {
+            self.field.arrow_scale()
+        } else {
+            self.field.arrow_scale() / self.factor.abs()
+        }
+    }
+
+    fn is_animated(&self) -> bool {
+        self.field.is_animated()
+    }
+}
+
+/// Another field turned `degrees` about the centre, and a further `spin`
+/// degrees every second.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rotate {
+    pub degrees: f32,
+    #[serde(default)]
+    pub spin: f32,
+    pub field: Box<Field>,
+}
+
+impl VectorField for Rotate {
+    fn force(&self, pos: Vec2, time: f32) -> Vec2 {
+        let rotation = Vec2::from_angle((self.degrees + self.spin * time).to_radians());
+        let unrotate = Vec2::new(rotation.x, -rotation.y);
+        rotation.rotate(self.field.force(unrotate.rotate(pos), time))
+    }
+
+    fn name(&self) -> &'static str {
+        self.field.name()
+    }
+
+    fn arrow_scale(&self) -> f32 {
+        self.field.arrow_scale()
+    }
+
+    fn is_animated(&self) -> bool {
+        self.spin != 0.0 || self.field.is_animated()
+    }
+}
+
+/// Another field with its centre moved by `offset`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Translate {
+    pub offset: [f32; 2],
+    pub field: Box<Field>,
+}
+
+impl VectorField for Translate {
+    fn force(&self, pos: Vec2, time: f32) -> Vec2 {
+        self.field.force(pos - Vec2::from_array(self.offset), time)
+    }
+
+    fn name(&self) -> &'static str {
+        self.field.name()
+    }
+
+    fn arrow_scale(&self) -> f32 {
+        self.field.arrow_scale()
+    }
+
+    fn is_animated(&self) -> bool {
+        self.field.is_animated()
+    }
+}
+
+/// Eases from one field into another and back again, once every `period`
+/// seconds.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Blend {
+    pub from: Box<Field>,
+    pub to: Box<Field>,
+    pub period: f32,
+}
+
+impl VectorField for Blend {
+    fn force(&self, pos: Vec2, time: f32) -> Vec2 {
+        let t = (1.0 - (TAU * time / self.period).cos()) / 2.0;
+        self.from.force(pos, time).lerp(self.to.force(pos, time), t)
+    }
+
+    fn name(&self) -> &'static str {
+        "blend"
+    }
+
+    fn arrow_scale(&self) -> f32 {
+        self.from.arrow_scale().min(self.to.arrow_scale())
+    }
+
+    fn is_animated(&self) -> bool {
+        true
+    }
+}
+
+/// Where an expression field is sampled to check for NaNs and infinities.
+/// Offset by half a step so that `1/z` isn't rejected just because a sample
+/// lands exactly on its pole. Only the start of the run is checked; later on
+/// the field is just zero wherever it blows up.
+const VALIDATE_EXTENT: f32 = 1000.0;
+const VALIDATE_STEP: f32 = 50.0;
+
+/// An expression compiled once when the map is loaded. `zoom` is how many
+/// pixels make one unit of the expression's input.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "ExpressionDef", into = "ExpressionDef")]
+pub struct ExpressionField {
+    pub source: String,
+    pub zoom: f32,
+    pub strength: f32,
+    program: Program,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct ExpressionDef {
+    source: String,
+    #[serde(default = "default_expression_zoom")]
+    zoom: f32,
+    #[serde(default = "default_expression_strength")]
+    strength: f32,
+}
+
+fn default_expression_zoom() -> f32 {
+    100.0
+}
+
+fn default_expression_strength() -> f32 {
+    100.0
+}
+
+impl ExpressionField {
+    pub fn new(source: &str, zoom: f32, strength: f32) -> anyhow::Result<ExpressionField> {
+        let field = ExpressionField {
+            source: source.to_owned(),
+            zoom,
+            strength,
+            program: Program::compile(source)?,
+        };
+        let mut y = -VALIDATE_EXTENT + VALIDATE_STEP / 2.0;
+        while y < VALIDATE_EXTENT {
+            let mut x = -VALIDATE_EXTENT + VALIDATE_STEP / 2.0;
+            while x < VALIDATE_EXTENT {
+                if !field.sample(Vec2::new(x, y), 0.0).is_finite() {
+                    bail!("the field isn't finite at ({}, {})", x, y);
+                }
+                x += VALIDATE_STEP;
+            }
+            y += VALIDATE_STEP;
+        }
+        Ok(field)
+    }
+
+    fn sample(&self, pos: Vec2, time: f32) -> Vec2 {
+        self.strength * self.program.eval(pos / self.zoom, time)
+    }
+}
+
+impl VectorField for ExpressionField {
+    /// Like sampling the expression, but zero wherever it blows up.
+    fn force(&self, pos: Vec2, time: f32) -> Vec2 {
+        let force = self.sample(pos, time);
+        if force.is_finite() {
+            force
+        } else {
+            Vec2::ZERO
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "expression"
+    }
+
+    fn is_animated(&self) -> bool {
+        self.program.uses_time()
+    }
+}
+
+impl PartialEq for ExpressionField {
+    fn eq(&self, other: &ExpressionField) -> bool {
+        self.source == other.source && self.zoom == other.zoom && self.strength == other.strength
+    }
+}
+
+impl TryFrom<ExpressionDef> for ExpressionField {
+    type Error = anyhow::Error;
+
+    fn try_from(def: ExpressionDef) -> anyhow::Result<ExpressionField> {
+        ExpressionField::new(&def.source, def.zoom, def.strength)
+    }
+}
+
+impl From<ExpressionField> for ExpressionDef {
+    fn from(field: ExpressionField) -> ExpressionDef {
+        ExpressionDef {
+            source: field.source,
+            zoom: field.zoom,
+            strength: field.strength,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(json: &str) -> anyhow::Result<()> {
+        serde_json::from_str::<Field>(json)?.check()
+    }
+
+    #[test]
+    fn checks_periods() {
+        assert!(check(r#"{ "type": "pulse", "strength": 100.0, "period": 2.0 }"#).is_ok());
+        assert!(check(r#"{ "type": "pulse", "strength": 100.0, "period": 0.0 }"#).is_err());
+        assert!(check(r#"{ "type": "pulse", "strength": 100.0, "period": -2.0 }"#).is_err());
+        assert!(check(r#"{ "type": "vortex", "strength": 100.0, "radius": 50.0 }"#).is_ok());
+        assert!(
+            check(r#"{ "type": "vortex", "strength": 100.0, "radius": 50.0, "period": 0.0 }"#)
+                .is_err()
+        );
+        let blend = |period: f32| {
+            format!(
+                r#"{{ "type": "blend", "period": {}, "from": {{ "type": "circular", "strength": 1.0 }}, "to": {{ "type": "circular", "strength": 2.0 }} }}"#,
+                period
+            )
+        };
+        assert!(check(&blend(4.0)).is_ok());
+        assert!(check(&blend(0.0)).is_err());
+
+        let mut pulse = Pulse {
+            strength: 100.0,
+            period: f32::NAN,
+        };
+        assert!(Field::Pulse(pulse.clone()).check().is_err());
+        pulse.period = f32::INFINITY;
+        assert!(Field::Pulse(pulse.clone()).check().is_err());
+
+        // however deep in another field it is
+        pulse.period = 0.0;
+        let nested = Field::Sum(Sum {
+            fields: vec![
+                Field::Circular(Circular { strength: 1.0 }),
+                Field::Rotate(Rotate {
+                    degrees: 90.0,
+                    spin: 0.0,
+                    field: Box::new(Field::Pulse(pulse)),
+                }),
+            ],
+        });
+        assert!(nested.check().is_err());
+    }
+}
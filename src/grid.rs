@@ -0,0 +1,131 @@
+use macroquad::math::Vec2;
+
+use crate::field::VectorField;
+
+/// A field sampled onto a grid of points `cell` pixels apart, so that looking
+/// it up is a bilinear interpolation between four of them instead of
+/// evaluating the field. Positions off the edge of the grid get the force at
+/// the nearest edge.
+#[derive(Clone, Debug)]
+pub struct FieldGrid {
+    min: Vec2,
+    cell: f32,
+    cols: usize,
+    rows: usize,
+    /// the time the field was sampled at
+    time: f32,
+    forces: Vec<Vec2>,
+}
+
+impl FieldGrid {
+    /// Samples `field` at `time` over the rectangle from `min` to `max`.
+    pub fn bake(field: &dyn VectorField, min: Vec2, max: Vec2, cell: f32, time: f32) -> FieldGrid {
+        let size = ((max - min) / cell).ceil().max(Vec2::ONE);
+        let (cols, rows) = (size.x as usize + 1, size.y as usize + 1);
+        let mut forces = Vec::with_capacity(cols * rows);
+        for row in 0..rows {
+            for col in 0..cols {
+                let pos = min + cell * Vec2::new(col as f32, row as f32);
+                forces.push(field.force(pos, time));
+            }
+        }
+        FieldGrid {
+            min,
+            cell,
+            cols,
+            rows,
+            time,
+            forces,
+        }
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn sample(&self, pos: Vec2) -> Vec2 {
+        // there are always at least two points each way, so every position
+        // falls in some cell
+        let max = Vec2::new((self.cols - 1) as f32, (self.rows - 1) as f32);
+        let at = ((pos - self.min) / self.cell).clamp(Vec2::ZERO, max);
+        let col = (at.x as usize).min(self.cols - 2);
+        let row = (at.y as usize).min(self.rows - 2);
+        let t = at - Vec2::new(col as f32, row as f32);
+        let i = row * self.cols + col;
+        let top = self.forces[i].lerp(self.forces[i + 1], t.x);
+        let bottom = self.forces[i + self.cols].lerp(self.forces[i + self.cols + 1], t.x);
+        top.lerp(bottom, t.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::{Field, Square};
+
+    /// Pushes harder the further right and down it is, which bilinear
+    /// lookup gets exactly right anywhere.
+    struct Slope;
+
+    impl VectorField for Slope {
+        fn force(&self, pos: Vec2, time: f32) -> Vec2 {
+            Vec2::new(pos.x + time, 2.0 * pos.y - pos.x)
+        }
+
+        fn name(&self) -> &'static str {
+            "slope"
+        }
+    }
+
+    #[test]
+    fn matches_the_field_at_grid_points() {
+        let field = Field::Square(Square {
+            scale: 0.01,
+            c: [-4.0, 0.0],
+        });
+        let (min, cell) = (Vec2::new(-100.0, -50.0), 10.0);
+        let grid = FieldGrid::bake(&field, min, Vec2::new(100.0, 50.0), cell, 0.0);
+        for row in 0..=10 {
+            for col in 0..=20 {
+                let pos = min + cell * Vec2::new(col as f32, row as f32);
+                assert!(grid.sample(pos).distance(field.force(pos, 0.0)) < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn interpolates_between_grid_points() {
+        let grid = FieldGrid::bake(&Slope, Vec2::ZERO, Vec2::new(100.0, 60.0), 20.0, 1.0);
+        assert_eq!(grid.time(), 1.0);
+        for pos in [
+            Vec2::new(5.0, 7.0),
+            Vec2::new(33.3, 41.0),
+            Vec2::new(99.0, 59.0),
+            Vec2::new(50.0, 30.0),
+        ] {
+            assert!(grid.sample(pos).distance(Slope.force(pos, 1.0)) < 1e-3);
+        }
+    }
+
+    #[test]
+    fn clamps_outside_the_grid() {
+        let grid = FieldGrid::bake(&Slope, Vec2::ZERO, Vec2::new(100.0, 60.0), 20.0, 0.0);
+        let edge = |pos: Vec2| Slope.force(pos, 0.0);
+        assert_eq!(
+            grid.sample(Vec2::new(-50.0, 30.0)),
+            edge(Vec2::new(0.0, 30.0))
+        );
+        assert_eq!(
+            grid.sample(Vec2::new(500.0, 30.0)),
+            edge(Vec2::new(100.0, 30.0))
+        );
+        assert_eq!(
+            grid.sample(Vec2::new(40.0, -1e6)),
+            edge(Vec2::new(40.0, 0.0))
+        );
+        assert_eq!(
+            grid.sample(Vec2::new(1e6, 1e6)),
+            edge(Vec2::new(100.0, 60.0))
+        );
+    }
+}
@@ -0,0 +1,18 @@
+pub mod enemy;
+pub mod expr;
+pub mod field;
+pub mod grid;
+pub mod map;
+pub mod mode;
+pub mod obstacle;
+pub mod physics;
+pub mod profile;
+pub mod replay;
+pub mod rng;
+pub mod scoring;
+pub mod sim;
+pub mod spatial;
+pub mod stats;
+pub mod verify;
+pub mod wave;
+pub mod weapon;
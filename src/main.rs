@@ -1,22 +1,32 @@
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 use macroquad::audio;
 use macroquad::audio::play_sound_once;
 use macroquad::audio::Sound;
 use macroquad::prelude::*;
-use macroquad::rand::*;
 use macroquad::ui::hash;
 use macroquad::ui::root_ui;
-use noise::NoiseFn;
-use noise::OpenSimplex;
-use serde::{Deserialize, Serialize};
 use serde_json::json;
 use serde_json::Value;
 
+use flowfield::field::{ExpressionField, Field, VectorField};
+use flowfield::map::{load_maps, Map, MAP_DIR};
+use flowfield::mode::GameMode;
+use flowfield::obstacle::{Obstacle, Shape};
+use flowfield::profile::{format_date, Profile, Run, Settings};
+use flowfield::replay::{Replay, ReplayPlayer};
+use flowfield::rng::Rng;
+use flowfield::scoring::Breakdown;
+use flowfield::sim::*;
+use flowfield::stats::{Second, Stats, HEAT_CELL};
+use flowfield::verify::{Score, Submission};
+use flowfield::wave::TELEGRAPH_SECS;
+
 // there's a menu
 // you can choose different field
 // there's an end screen with stats
-// you can view session best
+// you can view personal bests and past runs
 // you can submit score to db from end screen
 // you can view top scores from db
 // hit objects fade away?
@@ -24,121 +34,450 @@ use serde_json::Value;
 // sfx
 // make stuff look better idk
 
-struct Body {
-    pos: Vec2,
-    vel: Vec2,
-    acc: Vec2,
+/// How the field is drawn behind everything else.
+#[derive(Clone, Copy, PartialEq)]
+enum FieldView {
+    Arrows,
+    HeatMap,
+    Streamlines,
+    Particles,
+}
+
+impl FieldView {
+    const ALL: [FieldView; 4] = [
+        FieldView::Arrows,
+        FieldView::HeatMap,
+        FieldView::Streamlines,
+        FieldView::Particles,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            FieldView::Arrows => "arrows",
+            FieldView::HeatMap => "heat map",
+            FieldView::Streamlines => "streamlines",
+            FieldView::Particles => "particles",
+        }
+    }
+
+    fn next(self) -> FieldView {
+        match self {
+            FieldView::Arrows => FieldView::HeatMap,
+            FieldView::HeatMap => FieldView::Streamlines,
+            FieldView::Streamlines => FieldView::Particles,
+            FieldView::Particles => FieldView::Arrows,
+        }
+    }
+}
+
+const ARROW_SPACING: f32 = 50.0;
+const HEAT_MAP_CELL: f32 = 25.0;
+const STREAMLINE_SPACING: f32 = 80.0;
+const STREAMLINE_STEP: f32 = 6.0;
+const STREAMLINE_STEPS: usize = 30;
+const NUM_PARTICLES: usize = 800;
+/// how many arrow lengths a particle drifts in a second
+const PARTICLE_SPEED: f32 = 4.0;
+const PARTICLE_LIFETIME: f32 = 4.0;
+
+/// how quickly the camera catches up with the player, per second
+const CAMERA_FOLLOW_RATE: f32 = 5.0;
+const MINIMAP_SIZE: Vec2 = Vec2::new(240.0, 160.0);
+const MINIMAP_MARGIN: f32 = 20.0;
+const MINIMAP_ARROWS: usize = 16;
+/// the end screen's timelines, and the width of its heat map
+const CHART_SIZE: Vec2 = Vec2::new(400.0, 60.0);
+const CHART_GAP: f32 = 30.0;
+const CHART_MARGIN: f32 = 40.0;
+/// how many times a second the player blinks while invulnerable
+const INVULNERABLE_BLINK: f32 = 10.0;
+
+/// Shows an `ARENA`-sized part of the arena, as large as it fits in the
+/// window. Follows the player around arenas bigger than that, easing after
+/// them rather than sticking to them.
+struct FollowCamera {
+    center: Vec2,
+}
+
+impl FollowCamera {
+    fn new() -> FollowCamera {
+        FollowCamera {
+            center: ARENA / 2.0,
+        }
+    }
+
+    fn update(&mut self, arena: Vec2, target: Vec2, dt: f32) {
+        let ease = 1.0 - (-CAMERA_FOLLOW_RATE * dt).exp();
+        self.center += (target - self.center) * ease;
+        // keep the edges of the arena at the edges of the screen, and centre
+        // arenas that are smaller than the view
+        let half = ARENA / 2.0;
+        self.center = Vec2::select(
+            arena.cmple(ARENA),
+            arena / 2.0,
+            self.center.clamp(half, (arena - half).max(half)),
+        );
+    }
+
+    fn scale(&self) -> f32 {
+        (Vec2::new(screen_width(), screen_height()) / ARENA).min_element()
+    }
+
+    fn camera(&self) -> Camera2D {
+        Camera2D {
+            target: self.center,
+            zoom: 2.0 * self.scale() / Vec2::new(screen_width(), screen_height()),
+            ..Default::default()
+        }
+    }
+
+    /// The part of the arena that's on screen.
+    fn visible(&self, arena: Vec2) -> Rect {
+        let view = Rect::new(
+            self.center.x - ARENA.x / 2.0,
+            self.center.y - ARENA.y / 2.0,
+            ARENA.x,
+            ARENA.y,
+        );
+        view.intersect(Rect::new(0.0, 0.0, arena.x, arena.y))
+            .unwrap_or(view)
+    }
+
+    /// Covers everything outside the view, in screen coordinates.
+    fn draw_letterbox(&self, color: Color) {
+        let (width, height) = (screen_width(), screen_height());
+        let view = ARENA * self.scale();
+        let (x, y) = ((width - view.x) / 2.0, (height - view.y) / 2.0);
+        draw_rectangle(0.0, 0.0, width, y, color);
+        draw_rectangle(0.0, y + view.y, width, height - y - view.y, color);
+        draw_rectangle(0.0, y, x, view.y, color);
+        draw_rectangle(x + view.x, y, width - x - view.x, view.y, color);
+    }
+}
+
+/// The points `spacing` apart, starting from `offset` in both directions,
+/// that fall inside `rect`.
+fn grid_points(rect: Rect, spacing: f32, offset: f32) -> impl Iterator<Item = Vec2> {
+    let first = |min: f32| ((min - offset) / spacing).ceil().max(0.0) as usize;
+    let last = |max: f32| ((max - offset) / spacing).floor().max(-1.0) as isize + 1;
+    let cols = first(rect.x)..last(rect.right()).max(0) as usize;
+    let rows = first(rect.y)..last(rect.bottom()).max(0) as usize;
+    rows.flat_map(move |row| {
+        cols.clone()
+            .map(move |col| offset + spacing * Vec2::new(col as f32, row as f32))
+    })
+}
+
+fn draw_vector_field(world: &World, view: FieldView, visible: Rect, particles: &mut Particles) {
+    match view {
+        FieldView::Arrows => draw_arrows(world, visible, Color::from_hex(world.map.colors.field)),
+        FieldView::HeatMap => {
+            draw_heat_map(world, visible);
+            draw_arrows(world, visible, Color::from_hex(world.map.colors.background));
+        }
+        FieldView::Streamlines => draw_streamlines(world, visible),
+        FieldView::Particles => particles.draw(world, visible, get_frame_time()),
+    }
+}
+
+/// Points along the field from `pos`, as long as the arrow for that spot
+/// should be.
+fn arrow_at(world: &World, pos: Vec2) -> Vec2 {
+    let arrow = world.map.field.arrow_scale() * world.field_force(pos);
+    arrow.clamp_length_max(0.9 * ARROW_SPACING)
 }
 
-impl Body {
-    fn new(pos: Vec2, vel: Vec2, acc: Vec2) -> Body {
-        Body { pos, vel, acc }
+fn draw_arrow(start: Vec2, end: Vec2, color: Color) {
+    let dir = end - start;
+    draw_line(start.x, start.y, end.x, end.y, 1.0, color);
+    let head = 0.3 * dir.length().min(20.0);
+    let back = -dir.normalize_or_zero() * head;
+    for side in [Vec2::from_angle(0.5), Vec2::from_angle(-0.5)] {
+        let tip = end + side.rotate(back);
+        draw_line(end.x, end.y, tip.x, tip.y, 1.0, color);
     }
+}
 
-    fn update_position(&mut self, dt: f32) {
-        self.vel += dt * self.acc;
-        self.pos += dt * self.vel;
+fn draw_arrows(world: &World, visible: Rect, color: Color) {
+    for center in grid_points(visible, ARROW_SPACING, 0.0) {
+        let arrow = arrow_at(world, center);
+        draw_arrow(center - arrow / 2.0, center + arrow / 2.0, color);
     }
+}
 
-    fn is_in_bounds(&self) -> bool {
-        self.pos.x >= 0.0
-            && self.pos.x <= screen_width()
-            && self.pos.y >= 0.0
-            && self.pos.y <= screen_height()
+/// Shades the arena from the background colour where the field is weakest to
+/// the field colour where it's strongest, out of what's on screen.
+fn draw_heat_map(world: &World, visible: Rect) {
+    let cells: Vec<(Vec2, f32)> = grid_points(visible, HEAT_MAP_CELL, HEAT_MAP_CELL / 2.0)
+        .map(|center| (center, world.field_force(center).length()))
+        .collect();
+    let max = cells
+        .iter()
+        .map(|(_, magnitude)| *magnitude)
+        .fold(0.0, f32::max)
+        .max(f32::EPSILON);
+    let (low, high) = (
+        Color::from_hex(world.map.colors.background),
+        Color::from_hex(world.map.colors.field),
+    );
+    for (center, magnitude) in cells {
+        let t = magnitude / max;
+        let color = Color::new(
+            low.r + t * (high.r - low.r),
+            low.g + t * (high.g - low.g),
+            low.b + t * (high.b - low.b),
+            1.0,
+        );
+        let corner = center - HEAT_MAP_CELL / 2.0;
+        draw_rectangle(corner.x, corner.y, HEAT_MAP_CELL, HEAT_MAP_CELL, color);
     }
+}
 
-    fn bounds_clamp(&mut self) {
-        if self.pos.x < 0.0 {
-            self.pos.x = 0.0;
-            self.vel.x = BOUNCE_BOOST * PLAYER_MAX_MOVEMENT_SPEED;
-            self.acc.x = 0.0;
+/// Follows the field a little way forwards and backwards from points spread
+/// over the arena, with an arrowhead where each line ends.
+fn draw_streamlines(world: &World, visible: Rect) {
+    let color = Color::from_hex(world.map.colors.field);
+    for seed in grid_points(visible, STREAMLINE_SPACING, STREAMLINE_SPACING / 2.0) {
+        let mut line = vec![seed];
+        for dir in [-1.0, 1.0] {
+            let mut pos = seed;
+            let mut points = vec![];
+            for _ in 0..STREAMLINE_STEPS {
+                let step = world.field_force(pos).normalize_or_zero() * STREAMLINE_STEP;
+                if step == Vec2::ZERO {
+                    break;
+                }
+                pos += dir * step;
+                points.push(pos);
+            }
+            if dir < 0.0 {
+                points.reverse();
+                line.splice(0..0, points);
+            } else {
+                line.extend(points);
+            }
+        }
+        for pair in line.windows(2) {
+            draw_line(pair[0].x, pair[0].y, pair[1].x, pair[1].y, 1.0, color);
+        }
+        if let [.., before, end] = line[..] {
+            draw_arrow(before, end, color);
         }
-        if self.pos.x > screen_width() {
-            self.pos.x = screen_width();
-            self.vel.x = -BOUNCE_BOOST * PLAYER_MAX_MOVEMENT_SPEED;
-            self.acc.x = 0.0;
+    }
+}
+
+struct Particle {
+    pos: Vec2,
+    age: f32,
+}
+
+/// Specks that drift along with the field and fade out, like a wind map.
+/// They only live on the part of the arena that's on screen.
+struct Particles {
+    particles: Vec<Particle>,
+    rng: Rng,
+}
+
+impl Particles {
+    fn new() -> Particles {
+        Particles {
+            particles: vec![],
+            rng: Rng::new(0),
         }
-        if self.pos.y < 0.0 {
-            self.pos.y = 0.0;
-            self.vel.y = BOUNCE_BOOST * PLAYER_MAX_MOVEMENT_SPEED;
-            self.acc.y = 0.0;
+    }
+
+    fn random_pos(&mut self, visible: Rect) -> Vec2 {
+        let mut random = |max: f32| (self.rng.rand() % 10000) as f32 / 10000.0 * max;
+        Vec2::new(visible.x + random(visible.w), visible.y + random(visible.h))
+    }
+
+    fn draw(&mut self, world: &World, visible: Rect, dt: f32) {
+        // start everything at a different age so they don't all respawn at
+        // once
+        while self.particles.len() < NUM_PARTICLES {
+            let pos = self.random_pos(visible);
+            let age = (self.rng.rand() % 1000) as f32 / 1000.0 * PARTICLE_LIFETIME;
+            self.particles.push(Particle { pos, age });
         }
-        if self.pos.y > screen_height() {
-            self.pos.y = screen_height();
-            self.vel.y = -BOUNCE_BOOST * PLAYER_MAX_MOVEMENT_SPEED;
-            self.acc.y = 0.0;
+        let color = Color::from_hex(world.map.colors.field);
+        for i in 0..self.particles.len() {
+            let Particle { pos, age } = self.particles[i];
+            let vel = PARTICLE_SPEED * world.map.field.arrow_scale() * world.field_force(pos);
+            let next = pos + vel * dt;
+            if age + dt > PARTICLE_LIFETIME || !visible.contains(next) {
+                self.particles[i] = Particle {
+                    pos: self.random_pos(visible),
+                    age: 0.0,
+                };
+                continue;
+            }
+            self.particles[i] = Particle {
+                pos: next,
+                age: age + dt,
+            };
+            // fade in and out rather than popping
+            let fade = (age / PARTICLE_LIFETIME * std::f32::consts::PI).sin();
+            let tail = next - 0.1 * vel;
+            let color = Color { a: fade, ..color };
+            draw_line(tail.x, tail.y, next.x, next.y, 2.0, color);
         }
     }
 }
 
-const PLAYER_MOVEMENT: f32 = 1000.0;
-const PLAYER_MAX_MOVEMENT_SPEED: f32 = 1000.0;
-const PROJECTILE_INIT_SPEED: f32 = 1.5 * PLAYER_MAX_MOVEMENT_SPEED;
-const ENEMY_INIT_SPEED: f32 = 0.5 * PLAYER_MAX_MOVEMENT_SPEED;
-const ENEMY_INERTIA: f32 = 0.01;
-const FRICTION: f32 = 800.0;
-const VECTOR_FIELD_SCALAR: f32 = 0.01;
-const ENEMY_RADIUS: f32 = 50.0;
-const MAX_ENEMIES: usize = 5;
-const BOUNCE_BOOST: f32 = 1.0;
-const GAME_TIME_SECS: f32 = 30.0;
-
-fn translate_pos(pos: Vec2) -> Vec2 {
-    pos - Vec2::new(screen_width() / 2.0, screen_height() / 2.0)
-}
-
-type VectorFieldGetter = fn(macroquad::math::Vec2) -> macroquad::math::Vec2;
-
-const DUAL_VISION: &'static str = "dual vision";
-fn get_vector_field_force_basic(pos: Vec2) -> Vec2 {
-    let Vec2 { x, y } = translate_pos(pos);
-    VECTOR_FIELD_SCALAR * Vec2::new(x * x - y * y - 4.0, 2.0 * x * y)
-}
-
-const CURL_VALLEY: &'static str = "curl valley";
-fn get_vector_field_force_curl_noise(pos: Vec2) -> Vec2 {
-    const DERIVATIVE_SAMPLE: f64 = 0.001;
-    let Vec2 { x: _x, y: _y } = translate_pos(pos) / 400.0;
-    let x = _x as f64;
-    let y = _y as f64;
-    let noise = OpenSimplex::new(1);
-    let x1 = noise.get([x + DERIVATIVE_SAMPLE, y]);
-    let x2 = noise.get([x - DERIVATIVE_SAMPLE, y]);
-    let y1 = noise.get([x, y + DERIVATIVE_SAMPLE]);
-    let y2 = noise.get([x, y - DERIVATIVE_SAMPLE]);
-    let x_d = x2 - x1;
-    let y_d = y2 - y1;
-    let angle = y_d.atan2(x_d);
-    1000.0 * Vec2::from_angle(angle as f32)
-}
-
-const CLOCKBACK: &'static str = "clockback";
-fn get_vector_field_force_circular(pos: Vec2) -> Vec2 {
-    let Vec2 { x, y } = translate_pos(pos) / 400.0;
-    let angle = (-y / x).atan();
-    if x < 0.0 {
-        2000.0 * -Vec2::from_angle(-angle).rotate(-Vec2::Y)
-    } else {
-        2000.0 * Vec2::from_angle(-angle).rotate(-Vec2::Y)
-    }
-}
-
-fn draw_vector_field(get_vector_field_force: VectorFieldGetter) {
-    for x in (0..screen_width() as i32).step_by(50) {
-        for y in (0..screen_height() as i32).step_by(50) {
-            let start = Vec2::new(x as f32, y as f32);
-            let force = 0.01 * get_vector_field_force(start);
-            let end = start - force;
-            draw_circle(start.x, start.y, 2.0, Color::from_hex(0xDDA15E));
-            draw_line(
-                start.x,
-                start.y,
-                end.x,
-                end.y,
-                1.0,
-                Color::from_hex(0xDDA15E),
-            )
+/// A map of the whole arena in the bottom right corner of the screen, for
+/// arenas that don't fit on it.
+fn draw_minimap(world: &World, visible: Rect) {
+    if world.arena.cmple(ARENA).all() {
+        return;
+    }
+    let colors = &world.map.colors;
+    let scale = (MINIMAP_SIZE / world.arena).min_element();
+    let size = world.arena * scale;
+    let corner = Vec2::new(screen_width(), screen_height()) - MINIMAP_MARGIN - size;
+    let to_map = |pos: Vec2| corner + pos * scale;
+
+    let background = Color::from_hex(colors.background);
+    let field = Color::from_hex(colors.field);
+    draw_rectangle(corner.x, corner.y, size.x, size.y, background);
+    draw_rectangle_lines(corner.x, corner.y, size.x, size.y, 2.0, field);
+    let spacing = world.arena.max_element() / MINIMAP_ARROWS as f32;
+    for pos in grid_points(
+        Rect::new(0.0, 0.0, world.arena.x, world.arena.y),
+        spacing,
+        spacing / 2.0,
+    ) {
+        let half = world.field_force(pos).normalize_or_zero() * spacing * 0.4;
+        draw_arrow(to_map(pos - half), to_map(pos + half), field);
+    }
+    for obstacle in &world.map.obstacles {
+        draw_obstacle(obstacle, corner, scale, Color::from_hex(colors.obstacle));
+    }
+    for enemy in &world.enemies {
+        let pos = to_map(enemy.body.pos);
+        let color = enemy.color.unwrap_or(colors.enemy);
+        draw_circle(pos.x, pos.y, 2.0, Color::from_hex(color));
+    }
+    let player = to_map(world.player.pos);
+    draw_circle(player.x, player.y, 3.0, Color::from_hex(colors.player));
+    let view = to_map(visible.point());
+    let view_size = visible.size() * scale;
+    draw_rectangle_lines(view.x, view.y, view_size.x, view_size.y, 1.0, field);
+}
+
+/// Draws an obstacle at `scale` times its size with its origin moved to
+/// `offset`, so the same code does the arena and the minimap.
+fn draw_obstacle(obstacle: &Obstacle, offset: Vec2, scale: f32, color: Color) {
+    let to_screen = |point: [f32; 2]| offset + scale * Vec2::from_array(point);
+    match &obstacle.shape {
+        Shape::Circle { center, radius } => {
+            let center = to_screen(*center);
+            draw_circle(center.x, center.y, scale * radius, color);
+        }
+        Shape::Polygon { points } => {
+            let points: Vec<Vec2> = points.iter().map(|point| to_screen(*point)).collect();
+            for i in 1..points.len().saturating_sub(1) {
+                draw_triangle(points[0], points[i], points[i + 1], color);
+            }
+        }
+        Shape::Wall {
+            from,
+            to,
+            thickness,
+        } => {
+            let (from, to) = (to_screen(*from), to_screen(*to));
+            let width = scale * thickness;
+            draw_line(from.x, from.y, to.x, to.y, width, color);
+            draw_circle(from.x, from.y, width / 2.0, color);
+            draw_circle(to.x, to.y, width / 2.0, color);
+        }
+    }
+}
+
+fn draw_world(world: &World, alpha: f32) {
+    let colors = &world.map.colors;
+    for obstacle in &world.map.obstacles {
+        draw_obstacle(obstacle, Vec2::ZERO, 1.0, Color::from_hex(colors.obstacle));
+    }
+    let player = world.player.lerp_pos(alpha);
+    // blink while the player can't be hit
+    if world.invulnerable <= 0.0 || ((world.time * INVULNERABLE_BLINK) as u32).is_multiple_of(2) {
+        draw_circle(
+            player.x,
+            player.y,
+            PLAYER_RADIUS,
+            Color::from_hex(colors.player),
+        );
+    }
+
+    world.projectiles.iter().for_each(|projectile| {
+        let pos = projectile.body.lerp_pos(alpha);
+        draw_circle(
+            pos.x,
+            pos.y,
+            PROJECTILE_RADIUS,
+            Color::from_hex(colors.projectile),
+        )
+    });
+
+    // a ring on the edge where each enemy is about to come in, closing in
+    // as it gets nearer
+    for telegraph in &world.telegraphs {
+        let pos = telegraph.pos.clamp(Vec2::ZERO, world.arena);
+        let kind = telegraph.enemy.and_then(|i| world.map.enemies.types.get(i));
+        let color = kind.and_then(|kind| kind.color).unwrap_or(colors.enemy);
+        let radius = kind.map_or(world.map.enemies.radius, |kind| kind.radius);
+        let closing = telegraph.secs_left / TELEGRAPH_SECS;
+        draw_circle_lines(
+            pos.x,
+            pos.y,
+            radius * (1.0 + closing),
+            3.0,
+            Color::from_hex(color),
+        );
+    }
+
+    world.enemies.iter().for_each(|enemy| {
+        let pos = enemy.body.lerp_pos(alpha);
+        let color = enemy.color.unwrap_or(colors.enemy);
+        draw_circle(pos.x, pos.y, enemy.radius, Color::from_hex(color));
+        // a ring for every hit it can still take after the next one
+        for ring in 1..enemy.hp {
+            let radius = enemy.radius * (1.0 - ring as f32 * 0.15);
+            draw_circle_lines(
+                pos.x,
+                pos.y,
+                radius,
+                2.0,
+                Color::from_hex(colors.background),
+            );
         }
+    });
+}
+
+/// Switch to the first weapon, the second, and so on.
+const WEAPON_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+/// `camera` is the one the arena is drawn with, for aiming in arena
+/// coordinates.
+fn read_input(camera: &Camera2D) -> Input {
+    Input {
+        up: is_key_down(KeyCode::W),
+        down: is_key_down(KeyCode::S),
+        left: is_key_down(KeyCode::A),
+        right: is_key_down(KeyCode::D),
+        aim: camera.screen_to_world(mouse_position().into()),
+        fire: is_mouse_button_down(MouseButton::Left),
+        weapon: WEAPON_KEYS.iter().position(|&key| is_key_pressed(key)),
     }
 }
 
@@ -176,43 +515,226 @@ fn draw_score_at(text: &str, x: f32, y: f32, font: Option<&Font>) {
     draw_text_at(text, x, y, 12, font);
 }
 
-#[derive(PartialEq)]
+fn save_profile(profile: &Profile, path: &Path) {
+    if let Err(err) = profile.save(path) {
+        eprintln!("couldn't save profile {}: {}", path.display(), err);
+    }
+}
+
+/// Timelines of the run down the right of the end screen, then a map of
+/// where the player spent their time and where enemies were shot.
+fn draw_run_stats(world: &World, stats: &Stats, font: Option<&Font>) {
+    let colors = &world.map.colors;
+    let totals = stats.totals();
+    let per_second =
+        |value: fn(&Second) -> f32| stats.seconds.iter().map(value).collect::<Vec<f32>>();
+    let moving = totals.fighting + totals.riding;
+    let fighting = if moving > 0.0 {
+        100.0 * totals.fighting / moving
+    } else {
+        0.0
+    };
+    let charts = [
+        (
+            format!("kills per second ({} in all)", totals.kills),
+            per_second(|second| second.kills as f32),
+            colors.enemy,
+        ),
+        (
+            format!("shots per second ({} in all)", totals.shots),
+            per_second(|second| second.shots as f32),
+            colors.projectile,
+        ),
+        (
+            format!("distance per second ({:.0} px in all)", totals.distance),
+            per_second(|second| second.distance),
+            colors.player,
+        ),
+        (
+            format!("fighting the flow ({:.0}% of the time)", fighting),
+            per_second(|second| second.fighting / (second.fighting + second.riding).max(TICK_DT)),
+            colors.field,
+        ),
+    ];
+    let x = screen_width() - CHART_SIZE.x - CHART_MARGIN;
+    let mut y = 200.0;
+    for (label, values, color) in charts {
+        draw_score_at(&label, x, y - 4.0, font);
+        draw_timeline(&values, Vec2::new(x, y), Color::from_hex(color));
+        y += CHART_SIZE.y + CHART_GAP;
+    }
+    draw_score_at(
+        "where you were, and where enemies were shot",
+        x,
+        y - 4.0,
+        font,
+    );
+    draw_run_heat_map(world, stats, Vec2::new(x, y));
+}
+
+/// A bar for each second, scaled so that the biggest fills the chart.
+fn draw_timeline(values: &[f32], corner: Vec2, color: Color) {
+    draw_rectangle_lines(corner.x, corner.y, CHART_SIZE.x, CHART_SIZE.y, 1.0, color);
+    let max = values.iter().copied().fold(0.0, f32::max);
+    if max <= 0.0 {
+        return;
+    }
+    let width = CHART_SIZE.x / values.len() as f32;
+    for (i, value) in values.iter().enumerate() {
+        let height = CHART_SIZE.y * value / max;
+        draw_rectangle(
+            corner.x + width * i as f32,
+            corner.y + CHART_SIZE.y - height,
+            width.max(1.0),
+            height,
+            color,
+        );
+    }
+}
+
+/// The arena shrunk to fit the width of the charts, shaded by how long the
+/// player spent in each part of it, with a dot wherever an enemy was shot.
+fn draw_run_heat_map(world: &World, stats: &Stats, corner: Vec2) {
+    let colors = &world.map.colors;
+    let scale = CHART_SIZE.x / world.arena.x;
+    let size = world.arena * scale;
+    let field = Color::from_hex(colors.field);
+    draw_rectangle(
+        corner.x,
+        corner.y,
+        size.x,
+        size.y,
+        Color::from_hex(colors.background),
+    );
+    let max = stats.heat.iter().copied().max().unwrap_or(0).max(1) as f32;
+    let cell = HEAT_CELL * scale;
+    for (i, &ticks) in stats.heat.iter().enumerate() {
+        if ticks == 0 {
+            continue;
+        }
+        let (col, row) = ((i % stats.cols) as f32, (i / stats.cols) as f32);
+        let mut color = Color::from_hex(colors.player);
+        color.a = ticks as f32 / max;
+        let x = corner.x + col * cell;
+        let y = corner.y + row * cell;
+        // cells on the far edges can hang over the arena
+        let w = cell.min(corner.x + size.x - x);
+        let h = cell.min(corner.y + size.y - y);
+        draw_rectangle(x, y, w, h, color);
+    }
+    for obstacle in &world.map.obstacles {
+        draw_obstacle(obstacle, corner, scale, Color::from_hex(colors.obstacle));
+    }
+    // enemies are often shot coming in from just outside
+    for &pos in &stats.kills {
+        let pos = corner + pos.clamp(Vec2::ZERO, world.arena) * scale;
+        draw_circle(pos.x, pos.y, 2.5, Color::from_hex(colors.enemy));
+    }
+    draw_rectangle_lines(corner.x, corner.y, size.x, size.y, 1.0, field);
+}
+
+#[derive(Clone, Copy, PartialEq)]
 enum Stage {
     Home,
     Play,
     End,
+    Replay,
+    History,
+}
+
+/// how much darker the bars around the arena are than its background
+const LETTERBOX_SHADE: f32 = 0.9;
+
+const CUSTOM_MAP: &str = "custom";
+const DEFAULT_CUSTOM_FIELD: &str = "f(z) = z^2 - 4";
+
+const REPLAY_SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+/// runs listed on each page of the history screen
+const HISTORY_PAGE: usize = 20;
+/// how far apart the maps on the home screen are
+const MAP_COLUMN: f32 = 160.0;
+const REPLAY_DIR: &str = "replays";
+
+fn replay_path(replay: &Replay) -> PathBuf {
+    let timestamp = macroquad::miniquad::date::now() as u64;
+    PathBuf::from(REPLAY_DIR).join(format!(
+        "{}-{}.json",
+        timestamp,
+        replay.map.replace(' ', "-")
+    ))
+}
+
+/// The value after `flag` on the command line, e.g. `--seed 1234`.
+fn arg(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+    }
+    None
+}
+
+fn random_seed() -> u64 {
+    (macroquad::miniquad::date::now() * 1000.0) as u64
+}
+
+/// The same for everyone playing on the same (UTC) day.
+fn daily_seed() -> u64 {
+    (macroquad::miniquad::date::now() / (24.0 * 60.0 * 60.0)) as u64
+}
+
+/// Where scores are fetched from and submitted to. Set `FLOWFIELD_SERVER` to
+/// use a local stand-in, e.g. `flowfield-verify --serve`.
+fn server_url() -> String {
+    std::env::var("FLOWFIELD_SERVER")
+        .unwrap_or_else(|_| "https://basic-hound-665.convex.site".to_owned())
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Score {
-    map: String,
-    name: String,
-    score: i32,
+/// Posts a score, and if it's turned down, says why.
+fn submit_score(client: &reqwest::blocking::Client, submission: &Submission) -> anyhow::Result<()> {
+    let response = client
+        .post(format!("{}/newScore", server_url()))
+        .body(json!(submission).to_string())
+        .send()?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = response.text().unwrap_or_default();
+    let error = serde_json::from_str::<Value>(&body)
+        .ok()
+        .and_then(|body| Some(body["error"].as_str()?.to_owned()));
+    Err(anyhow::anyhow!(error.unwrap_or_else(|| status.to_string())))
 }
 
-fn get_scores(top_scores: &mut HashMap<&'static str, Vec<Score>>) -> anyhow::Result<()> {
-    let body = reqwest::blocking::get("https://basic-hound-665.convex.site/topScores")?.text()?;
+fn get_scores(top_scores: &mut HashMap<String, Vec<Score>>, maps: &[Map]) -> anyhow::Result<()> {
+    let body = reqwest::blocking::get(format!("{}/topScores", server_url()))?.text()?;
     let v: Value = serde_json::from_str(&body)?;
-    for map in [DUAL_VISION, CURL_VALLEY, CLOCKBACK] {
-        let v = &v[map];
-        match v {
-            Value::Array(array) => {
+    for map in maps {
+        for mode in GameMode::ALL {
+            let leaderboard = mode.leaderboard(&map.name);
+            if let Value::Array(array) = &v[&leaderboard] {
                 let mut scores: Vec<Score> = vec![];
                 for v in array {
                     let score: Score = serde_json::from_value(v.clone())?;
                     scores.push(score);
                 }
-                top_scores.insert(map, scores);
+                top_scores.insert(leaderboard, scores);
             }
-            _ => (),
         }
     }
     Ok(())
 }
 
-fn draw_top_scores(scores: &Vec<Score>, x: f32, font: Option<&Font>) {
+fn draw_top_scores(scores: &[Score], x: f32, font: Option<&Font>) {
     scores.iter().enumerate().for_each(|(i, score)| {
-        let text = format!("{}. {:2} {:10}", i + 1, score.name, score.score);
+        let text = format!(
+            "{}. {:2} {:>10}",
+            i + 1,
+            score.name,
+            score.mode.format_score(score.score)
+        );
         draw_score_at(&text, x, 340.0 + (i as f32) * 20.0, font)
     })
 }
@@ -261,29 +783,75 @@ async fn main() {
     let font = load_ttf_font_from_bytes(include_bytes!("../DMSans-Regular.ttf")).ok();
     set_fullscreen(true);
 
-    let mut session_best_scores: HashMap<&'static str, i32> = HashMap::new();
-    let mut top_scores: HashMap<&'static str, Vec<Score>> = HashMap::new();
-    let _ = get_scores(&mut top_scores);
+    let mut maps = load_maps(MAP_DIR.as_ref());
+    // the last map is the one whose field the player types in on the home
+    // screen
+    let mut custom_source = DEFAULT_CUSTOM_FIELD.to_owned();
+    let mut custom_compiled = custom_source.clone();
+    let mut custom_error: Option<String> = None;
+    let custom_field = ExpressionField::new(&custom_source, 100.0, 100.0)
+        .expect("the default custom field is valid");
+    maps.push(Map::new(CUSTOM_MAP, Field::Expression(custom_field)));
+    let custom_map = maps.len() - 1;
+    // `--stress <n>` lets up to n enemies on at once on every map, for
+    // seeing how the game holds up with thousands of bodies
+    let stress: Option<usize> = arg("--stress").and_then(|max| max.parse().ok());
+    if let Some(max) = stress {
+        for map in &mut maps {
+            map.enemies.max = max;
+        }
+    }
+
+    let profile_path = Profile::path();
+    let mut profile = Profile::load_or_default(&profile_path);
+    let mut top_scores: HashMap<String, Vec<Score>> = HashMap::new();
+    let _ = get_scores(&mut top_scores, &maps);
 
     let mut stage = Stage::Home;
-    let mut current_map = DUAL_VISION;
-    let mut get_vector_field_force: VectorFieldGetter = get_vector_field_force_basic;
-
-    let mut secs_left = GAME_TIME_SECS;
-    let mut player = Body::new(
-        Vec2::new(screen_width() - 30.0, screen_height() - 30.0),
-        Vec2::ZERO,
-        Vec2::ZERO,
-    );
-    let mut projectiles: Vec<Body> = vec![];
-    let mut enemies: Vec<Body> = vec![];
-    let mut num_projectiles = 0;
-    let mut num_enemies_shot = 0;
-    let mut num_collisions = 0;
+    let settings = profile.settings.clone();
+    let mut current_map = settings
+        .map
+        .and_then(|name| maps.iter().position(|map| map.name == name))
+        .unwrap_or(0);
+    let mut mode = settings.mode;
+    let mut world = World::new(maps[current_map].clone(), random_seed());
+    // `--seed <n>` or `--daily` fixes the enemy sequence; left blank, every
+    // run gets a fresh seed
+    let mut seed_text = match arg("--seed") {
+        Some(seed) => seed,
+        None if std::env::args().any(|arg| arg == "--daily") => daily_seed().to_string(),
+        None => String::new(),
+    };
+    let mut sound_rng = Rng::new(0);
+    let mut replay = Replay::new(0, &maps[current_map], mode);
+    let mut stats = Stats::new(world.arena);
+    let mut replay_saved = false;
 
-    let mut player_initials: String = String::new();
+    let mut replay_player: Option<ReplayPlayer> = None;
+    let mut replay_step = FixedStep::default();
+    let mut replay_speed = 2;
+    let mut replay_paused = false;
+    let mut replay_return = Stage::Home;
+    // `--replay <file>` opens straight into the replay viewer
+    if let Some(path) = arg("--replay").map(PathBuf::from) {
+        match Replay::load(&path).and_then(|replay| ReplayPlayer::new(&replay, &maps)) {
+            Ok(player) => {
+                sound_rng = Rng::new(player.world.seed);
+                replay_player = Some(player);
+                stage = Stage::Replay;
+            }
+            Err(err) => eprintln!("couldn't load replay {}: {}", path.display(), err),
+        }
+    }
+
+    let mut player_initials = profile.initials.clone();
+    let mut new_best = false;
+    let mut history_page = 0;
+    let mut map_page = 0;
     let reqwest_client = reqwest::blocking::Client::new();
     let mut score_submitted = false;
+    // why the last submission was turned down, if it was
+    let mut submit_error: Option<String> = None;
 
     let mut hit_sounds: Vec<Sound> = vec![];
     load_hit_sounds(&mut hit_sounds).await;
@@ -300,149 +868,189 @@ async fn main() {
         .await
         .ok();
 
-    loop {
-        let dt = get_frame_time();
-        clear_background(Color::from_hex(0xFEFAE0));
-        draw_vector_field(get_vector_field_force);
-
-        // PLAYER
-        player.acc = Vec2::ZERO;
+    let mut fixed_step = FixedStep::default();
+    let mut field_view = FieldView::ALL
+        .into_iter()
+        .find(|view| settings.field_view.as_deref() == Some(view.name()))
+        .unwrap_or(FieldView::Arrows);
+    let mut particles = Particles::new();
+    let mut follow = FollowCamera::new();
+    // a weapon key pressed on a frame too short for a tick is kept until
+    // the next tick, so it isn't lost
+    let mut weapon_pressed = None;
 
-        if is_key_down(KeyCode::D) {
-            player.acc.x += PLAYER_MOVEMENT;
-        }
-        if is_key_down(KeyCode::A) {
-            player.acc.x += -PLAYER_MOVEMENT;
-        }
-        if is_key_down(KeyCode::W) {
-            player.acc.y += -PLAYER_MOVEMENT;
-        }
-        if is_key_down(KeyCode::S) {
-            player.acc.y += PLAYER_MOVEMENT;
+    loop {
+        if is_key_pressed(KeyCode::Tab) {
+            field_view = field_view.next();
         }
 
-        player.acc += -player.vel.normalize_or_zero() * FRICTION * player.vel.length()
-            / PLAYER_MAX_MOVEMENT_SPEED;
-        player.acc += get_vector_field_force(player.pos);
-
-        player.bounds_clamp();
-        player.update_position(dt);
-        draw_circle(player.pos.x, player.pos.y, 15.0, Color::from_hex(0x22577a));
-
-        // PROJECTILES
-        if is_mouse_button_down(MouseButton::Left) {
-            let init_dir = Vec2::from_array(mouse_position().into()) - player.pos;
-            let init_vel = init_dir.normalize_or(Vec2::X) * PROJECTILE_INIT_SPEED;
-            projectiles.push(Body::new(player.pos, init_vel, Vec2::ZERO));
-            if stage == Stage::Play {
-                num_projectiles += 1;
+        let input = read_input(&follow.camera());
+        let mut events = vec![];
+        if stage == Stage::Replay {
+            // keys pressed while watching don't carry over to the world
+            weapon_pressed = None;
+            if let Some(player) = &mut replay_player {
+                if is_key_pressed(KeyCode::Space) {
+                    replay_paused = !replay_paused;
+                }
+                if is_key_pressed(KeyCode::Up) {
+                    replay_speed = (replay_speed + 1).min(REPLAY_SPEEDS.len() - 1);
+                }
+                if is_key_pressed(KeyCode::Down) {
+                    replay_speed = replay_speed.saturating_sub(1);
+                }
+                if is_key_pressed(KeyCode::Left) {
+                    player.seek(player.tick().saturating_sub(TICK_RATE as usize));
+                }
+                if is_key_pressed(KeyCode::Right) {
+                    player.seek(player.tick() + TICK_RATE as usize);
+                }
+                if !replay_paused {
+                    let frame_time = get_frame_time() * REPLAY_SPEEDS[replay_speed];
+                    for _ in 0..replay_step.advance(frame_time) {
+                        events.extend(player.step());
+                    }
+                }
             }
-            if let Some(shoot_sound) = &shoot_sound {
-                play_sound_once(shoot_sound);
+        } else {
+            weapon_pressed = input.weapon.or(weapon_pressed);
+            for _ in 0..fixed_step.advance(get_frame_time()) {
+                // only the first tick switches weapon
+                let input = Input {
+                    weapon: weapon_pressed.take(),
+                    ..input
+                };
+                let playing = world.playing;
+                if playing {
+                    replay.push(&input);
+                }
+                let step_events = world.step(TICK_DT, &input);
+                if playing {
+                    stats.record(&world, &input, &step_events);
+                }
+                events.extend(step_events);
             }
         }
 
-        projectiles
-            .iter_mut()
-            .for_each(|projectile| projectile.acc += get_vector_field_force(projectile.pos));
-
-        projectiles
-            .iter_mut()
-            .for_each(|projectile| projectile.update_position(dt));
-
-        projectiles.retain(|projectile| projectile.is_in_bounds());
-
-        projectiles.iter().for_each(|projectile| {
-            let before = enemies.len();
-            enemies.retain(|enemy| enemy.pos.distance(projectile.pos) > ENEMY_RADIUS);
-            let after = enemies.len();
-            if stage == Stage::Play {
-                num_enemies_shot += before - after;
-            }
-            if before - after > 0 {
-                if let Some(sound) = hit_sounds.get(gen_range(0, hit_sounds.len())) {
-                    play_sound_once(sound);
+        for event in events {
+            match event {
+                Event::Shot => {
+                    if let Some(shoot_sound) = &shoot_sound {
+                        play_sound_once(shoot_sound);
+                    }
                 }
-            }
-        });
-
-        projectiles.iter().for_each(|projectile| {
-            draw_circle(
-                projectile.pos.x,
-                projectile.pos.y,
-                5.0,
-                Color::from_hex(0xbc4749),
-            )
-        });
-
-        if stage == Stage::Play {
-            // ENEMIES
-            if enemies.len() < MAX_ENEMIES {
-                let pos_l = Vec2::new(-ENEMY_RADIUS, (rand() % screen_height() as u32) as f32);
-                let pos_r = Vec2::new(
-                    screen_width() + ENEMY_RADIUS,
-                    (rand() % screen_height() as u32) as f32,
-                );
-                let pos_u = Vec2::new((rand() % screen_height() as u32) as f32, -ENEMY_RADIUS);
-                let pos_d = Vec2::new(
-                    (rand() % screen_height() as u32) as f32,
-                    screen_height() + ENEMY_RADIUS,
-                );
-                let pos = [pos_d, pos_l, pos_r, pos_u][(rand() % 4) as usize];
-                let dir = player.pos - pos;
-                let vel = dir.normalize_or(Vec2::Y) * ENEMY_INIT_SPEED;
-                let enemy = Body::new(pos, vel, Vec2::ZERO);
-                enemies.push(enemy);
-            }
-
-            enemies
-                .iter_mut()
-                .for_each(|enemy| enemy.acc += ENEMY_INERTIA * get_vector_field_force(enemy.pos));
-
-            enemies
-                .iter_mut()
-                .for_each(|enemy| enemy.update_position(dt));
-
-            enemies.retain(|enemy| {
-                if enemy.pos.distance(player.pos) <= ENEMY_RADIUS {
+                Event::Hit => {
+                    let i = sound_rng.rand() as usize % hit_sounds.len().max(1);
+                    if let Some(sound) = hit_sounds.get(i) {
+                        play_sound_once(sound);
+                    }
+                }
+                Event::Killed(_) => {}
+                Event::Collision => {
                     if let Some(collision_sound) = &collision_sound {
                         play_sound_once(collision_sound);
                     }
-                    num_collisions += 1;
-                    return false;
                 }
-                enemy.pos.distance_squared(player.pos)
-                    <= screen_height() * screen_height() + screen_width() * screen_width()
-            });
+                Event::TimeUp | Event::Died | Event::Cleared => {
+                    if let Some(end_sound) = &end_sound {
+                        play_sound_once(end_sound)
+                    }
+                    if stage == Stage::Play {
+                        stage = Stage::End;
+                        // stress runs aren't on the real maps, so they
+                        // don't count either
+                        if stress.is_none() {
+                            new_best = profile.record(Run {
+                                map: replay.map.clone(),
+                                mode: world.mode,
+                                seed: replay.seed,
+                                finished: macroquad::miniquad::date::now(),
+                                secs: world.elapsed,
+                                breakdown: Breakdown::new(&world),
+                            });
+                            save_profile(&profile, &profile_path);
+                        }
+                    }
+                }
+            }
+        }
 
-            enemies.iter().for_each(|enemy| {
-                draw_circle(
-                    enemy.pos.x,
-                    enemy.pos.y,
-                    ENEMY_RADIUS,
-                    Color::from_hex(0xBC6C25),
-                )
-            });
+        let (shown, alpha) = match &replay_player {
+            Some(player) if stage == Stage::Replay => (&player.world, replay_step.alpha()),
+            _ => (&world, fixed_step.alpha()),
+        };
+        let background = Color::from_hex(shown.map.colors.background);
+        let letterbox =
+            Color::from_vec((background.to_vec().truncate() * LETTERBOX_SHADE).extend(1.0));
+        clear_background(letterbox);
+        follow.update(shown.arena, shown.player.lerp_pos(alpha), get_frame_time());
+        let visible = follow.visible(shown.arena);
+        set_camera(&follow.camera());
+        draw_rectangle(0.0, 0.0, shown.arena.x, shown.arena.y, background);
+        draw_vector_field(shown, field_view, visible, &mut particles);
+        draw_world(shown, alpha);
+        set_default_camera();
+        follow.draw_letterbox(letterbox);
+        draw_minimap(shown, visible);
 
+        if stage == Stage::Play || stage == Stage::Replay {
             draw_text_ul(
-                &format!("enemies shot {:.1}", num_enemies_shot),
+                &format!("enemies shot {:.1}", shown.num_enemies_shot),
                 0.0,
                 40.0,
                 font.as_ref(),
             );
             draw_text_ur(
-                &format!("time left {:.1} s", secs_left),
+                &shown.mode.clock(shown),
                 screen_width(),
                 40.0,
                 font.as_ref(),
             );
-
-            secs_left -= dt;
-            if secs_left <= 0.0 {
-                if let Some(end_sound) = &end_sound {
-                    play_sound_once(end_sound)
-                }
-                stage = Stage::End;
+            if let Some(waves) = &shown.map.waves {
+                draw_text_ur(
+                    &format!("wave {}", shown.waves.started(waves)),
+                    screen_width(),
+                    120.0,
+                    font.as_ref(),
+                );
+            }
+            if let Some(weapon) = shown
+                .map
+                .weapons
+                .get(shown.weapon)
+                .filter(|_| shown.mode.can_fire())
+            {
+                let ammo = match shown.ammo.get(shown.weapon) {
+                    Some(Some(ammo)) => format!(" ({})", ammo),
+                    _ => String::new(),
+                };
+                draw_text_ur(
+                    &format!("{} {}{}", shown.weapon + 1, weapon.name, ammo),
+                    screen_width(),
+                    80.0,
+                    font.as_ref(),
+                );
+            }
+            if shown.survival.is_some() {
+                draw_text_ul(
+                    &format!("health {} lives {}", shown.health, shown.lives),
+                    0.0,
+                    80.0,
+                    font.as_ref(),
+                );
+            }
+            if stress.is_some() {
+                draw_text_ul(
+                    &format!(
+                        "{} fps, {} enemies, {} projectiles",
+                        get_fps(),
+                        shown.enemies.len(),
+                        shown.projectiles.len()
+                    ),
+                    0.0,
+                    120.0,
+                    font.as_ref(),
+                );
             }
         }
 
@@ -451,67 +1059,104 @@ async fn main() {
             draw_text_ll("choose a field:", 80.0, 310.0, font.as_ref());
             if root_ui().button(
                 Some(Vec2::new(80.0, 500.0)),
-                format!("play ({})", current_map),
+                format!("play ({})", maps[current_map].name),
             ) {
                 stage = Stage::Play;
-                secs_left = GAME_TIME_SECS;
-                num_enemies_shot = 0;
-                num_projectiles = 0;
-                num_collisions = 0;
-                enemies = vec![];
+                let seed = seed_text.trim().parse().unwrap_or_else(|_| random_seed());
+                sound_rng = Rng::new(seed);
+                replay = Replay::new(seed, &maps[current_map], mode);
+                replay_saved = false;
+                if let Ok(run) = replay.world(&maps) {
+                    world = run;
+                }
+                stats = Stats::new(world.arena);
                 if let Some(start_sound) = &start_sound {
                     play_sound_once(start_sound)
                 }
             }
-            const SESSION_BEST_Y: f32 = 480.0;
-            draw_score_at("session best:", 80.0, SESSION_BEST_Y - 12.0, font.as_ref());
-            draw_score_at("session best:", 240.0, SESSION_BEST_Y - 12.0, font.as_ref());
-            draw_score_at("session best:", 400.0, SESSION_BEST_Y - 12.0, font.as_ref());
-
-            if root_ui().button(Some(Vec2::new(80.0, 300.0)), DUAL_VISION) {
-                current_map = DUAL_VISION;
-                get_vector_field_force = get_vector_field_force_basic;
-            }
-            let score = &session_best_scores
-                .get(DUAL_VISION)
-                .map_or("unplayed".to_owned(), |score| format!("{}", score));
-            draw_score_at(score, 80.0, SESSION_BEST_Y, font.as_ref());
-            draw_top_scores(
-                top_scores.get(DUAL_VISION).unwrap_or(&vec![]),
-                80.0,
-                font.as_ref(),
-            );
-
-            if root_ui().button(Some(Vec2::new(240.0, 300.0)), CURL_VALLEY) {
-                current_map = CURL_VALLEY;
-                get_vector_field_force = get_vector_field_force_curl_noise;
-            }
-            let score = &session_best_scores
-                .get(CURL_VALLEY)
-                .map_or("unplayed".to_owned(), |score| format!("{}", score));
-            draw_score_at(score, 240.0, SESSION_BEST_Y, font.as_ref());
-            draw_top_scores(
-                top_scores.get(CURL_VALLEY).unwrap_or(&vec![]),
-                240.0,
-                font.as_ref(),
-            );
-
-            if root_ui().button(Some(Vec2::new(400.0, 300.0)), CLOCKBACK) {
-                current_map = CLOCKBACK;
-                get_vector_field_force = get_vector_field_force_circular;
-            }
-            let score = &session_best_scores
-                .get(CLOCKBACK)
-                .map_or("unplayed".to_owned(), |score| format!("{}", score));
-            draw_score_at(score, 400.0, SESSION_BEST_Y, font.as_ref());
-            draw_top_scores(
-                top_scores.get(CLOCKBACK).unwrap_or(&vec![]),
-                400.0,
-                font.as_ref(),
-            );
+            root_ui().window(hash!(), Vec2::new(80., 540.), Vec2::new(300., 25.), |ui| {
+                ui.input_text(hash!(), "seed (blank for random)", &mut seed_text);
+            });
+            if root_ui().button(
+                Some(Vec2::new(400.0, 500.0)),
+                format!("field view: {} (tab)", field_view.name()),
+            ) {
+                field_view = field_view.next();
+            }
+            if root_ui().button(Some(Vec2::new(400.0, 540.0)), "daily seed") {
+                seed_text = daily_seed().to_string();
+            }
+            if root_ui().button(
+                Some(Vec2::new(560.0, 540.0)),
+                format!("mode: {}", mode.name()),
+            ) {
+                mode = mode.next();
+            }
+            if root_ui().button(Some(Vec2::new(560.0, 580.0)), "history") {
+                history_page = 0;
+                stage = Stage::History;
+            }
+            root_ui().window(hash!(), Vec2::new(80., 590.), Vec2::new(450., 25.), |ui| {
+                ui.input_text(hash!(), "custom field", &mut custom_source);
+            });
+            if custom_source != custom_compiled {
+                custom_compiled = custom_source.clone();
+                match ExpressionField::new(&custom_source, 100.0, 100.0) {
+                    Ok(field) => {
+                        custom_error = None;
+                        maps[custom_map].field = Field::Expression(field);
+                        current_map = custom_map;
+                        world = World::new(maps[custom_map].clone(), world.seed);
+                    }
+                    Err(err) => custom_error = Some(err.to_string()),
+                }
+            }
+            if let Some(err) = &custom_error {
+                draw_score_at(err, 80.0, 630.0, font.as_ref());
+            }
+            const PERSONAL_BEST_Y: f32 = 480.0;
+            // as many maps as fit across the screen, a page at a time
+            let per_page = (((screen_width() - 160.0) / MAP_COLUMN) as usize).max(1);
+            let pages = maps.len().div_ceil(per_page);
+            map_page = map_page.min(pages - 1);
+            if pages > 1 {
+                draw_score_at(
+                    &format!("page {} of {}", map_page + 1, pages),
+                    400.0,
+                    292.0,
+                    font.as_ref(),
+                );
+                if map_page > 0 && root_ui().button(Some(Vec2::new(480.0, 280.0)), "<") {
+                    map_page -= 1;
+                }
+                if map_page + 1 < pages && root_ui().button(Some(Vec2::new(500.0, 280.0)), ">") {
+                    map_page += 1;
+                }
+            }
+            let on_page = maps.iter().enumerate().skip(map_page * per_page);
+            for (column, (i, map)) in on_page.take(per_page).enumerate() {
+                let x = 80.0 + MAP_COLUMN * column as f32;
+                if root_ui().button(Some(Vec2::new(x, 300.0)), map.name.as_str()) {
+                    current_map = i;
+                    // a fresh world, sized and set up for the new map
+                    world = World::new(map.clone(), world.seed);
+                }
+                draw_score_at(map.field.name(), x, 330.0, font.as_ref());
+                let leaderboard = mode.leaderboard(&map.name);
+                draw_score_at("personal best:", x, PERSONAL_BEST_Y - 12.0, font.as_ref());
+                let score = &profile
+                    .best(&map.name, mode)
+                    .map_or("unplayed".to_owned(), |score| mode.format_score(score));
+                draw_score_at(score, x, PERSONAL_BEST_Y, font.as_ref());
+                draw_top_scores(
+                    top_scores.get(&leaderboard).unwrap_or(&vec![]),
+                    x,
+                    font.as_ref(),
+                );
+            }
 
             draw_text_ll(
-                "WASD to move, point and click to shoot",
+                "WASD to move, point and click to shoot, 1-5 to switch weapons",
                 80.0,
                 screen_height(),
                 font.as_ref(),
@@ -520,61 +1165,64 @@ async fn main() {
 
         if stage == Stage::Play {
             score_submitted = false;
+            submit_error = None;
         }
 
         if stage == Stage::End {
-            let final_score =
-                100 * (num_enemies_shot as i32) - num_projectiles - 1000 * (num_collisions);
-            let previous = session_best_scores.get(current_map).unwrap_or(&i32::MIN);
-            session_best_scores.insert(current_map, final_score.max(*previous));
+            let breakdown = Breakdown::new(&world);
+            let final_score = breakdown.score;
             draw_text_at("game over", 80.0, 200.0, 100, font.as_ref());
+            for (i, line) in world.mode.summary(&world).iter().enumerate() {
+                draw_text_ul(line, 80.0, 300.0 + 50.0 * i as f32, font.as_ref());
+            }
+            if world.mode.can_fire() {
+                let lines = breakdown.lines(world.mode.scores_bonuses());
+                for (i, line) in lines.iter().enumerate() {
+                    draw_text_ul(line, 700.0, 300.0 + 50.0 * i as f32, font.as_ref());
+                }
+            }
             draw_text_ul(
-                &format!("enemies shot 100 x {}", num_enemies_shot),
-                80.0,
-                300.0,
-                font.as_ref(),
-            );
-            draw_text_ul(
-                &format!("projectiles used -1 x {}", num_projectiles),
-                80.0,
-                350.0,
-                font.as_ref(),
-            );
-            draw_text_ul(
-                &format!("enemy collisions -1000 x {}", num_collisions),
-                80.0,
-                400.0,
-                font.as_ref(),
-            );
-            draw_text_ul(
-                &format!("final score {:0}", final_score),
+                &format!(
+                    "final score {}{}",
+                    world.mode.format_score(final_score),
+                    if new_best { " (personal best!)" } else { "" }
+                ),
                 80.0,
                 450.0,
                 font.as_ref(),
             );
-            if !score_submitted {
+            draw_score_at(&format!("seed {}", replay.seed), 80.0, 480.0, font.as_ref());
+            draw_run_stats(&world, &stats, font.as_ref());
+            // stress runs aren't on the real maps, so they can't be verified
+            if !score_submitted && stress.is_none() {
                 root_ui().window(hash!(), Vec2::new(80., 520.), Vec2::new(450., 25.), |ui| {
                     ui.input_text(hash!(), "enter initals", &mut player_initials);
                 });
                 player_initials = player_initials.chars().take(2).collect();
+                if player_initials != profile.initials {
+                    profile.initials = player_initials.clone();
+                    save_profile(&profile, &profile_path);
+                }
 
                 if root_ui().button(Some(Vec2::new(80.0, 550.0)), "submit score") {
-                    let json = json!({
-                        "map": current_map,
-                        "name": player_initials,
-                        "score": final_score,
-                    });
-                    if let Ok(result) = reqwest_client
-                        .post("https://basic-hound-665.convex.site/newScore")
-                        .body(json.to_string())
-                        .send()
-                    {
-                        if result.status().is_success() {
+                    let submission = Submission::new(&player_initials, &breakdown, &replay);
+                    match submit_score(&reqwest_client, &submission) {
+                        Ok(()) => {
                             score_submitted = true;
-                            let _ = get_scores(&mut top_scores);
+                            submit_error = None;
+                            let _ = get_scores(&mut top_scores, &maps);
                         }
+                        Err(err) => submit_error = Some(err.to_string()),
                     }
                 }
+                if let Some(err) = &submit_error {
+                    draw_score_at(
+                        &format!("couldn't submit score: {}", err),
+                        200.0,
+                        565.0,
+                        font.as_ref(),
+                    );
+                }
             }
 
             if score_submitted {
@@ -584,6 +1232,120 @@ async fn main() {
             if root_ui().button(Some(Vec2::new(80.0, 600.0)), "continue") {
                 stage = Stage::Home;
             }
+            if root_ui().button(Some(Vec2::new(80.0, 650.0)), "watch replay") {
+                if let Ok(player) = ReplayPlayer::new(&replay, &maps) {
+                    sound_rng = Rng::new(replay.seed);
+                    replay_player = Some(player);
+                    replay_step = FixedStep::default();
+                    replay_paused = false;
+                    replay_return = Stage::End;
+                    stage = Stage::Replay;
+                }
+            }
+            if !replay_saved {
+                if root_ui().button(Some(Vec2::new(80.0, 700.0)), "save replay") {
+                    replay_saved = replay.save(&replay_path(&replay)).is_ok();
+                }
+            } else {
+                draw_text_ul("replay saved", 80.0, 700.0, font.as_ref())
+            }
+        }
+
+        if stage == Stage::Replay {
+            if let Some(player) = &mut replay_player {
+                draw_text_ll(
+                    &format!(
+                        "replay {}x{}",
+                        REPLAY_SPEEDS[replay_speed],
+                        if replay_paused { " (paused)" } else { "" }
+                    ),
+                    0.0,
+                    screen_height(),
+                    font.as_ref(),
+                );
+                draw_score_at(
+                    "space to pause, left/right to skip, up/down to change speed",
+                    0.0,
+                    60.0,
+                    font.as_ref(),
+                );
+                let mut scrub = player.tick() as f32;
+                root_ui().window(
+                    hash!(),
+                    Vec2::new(screen_width() / 2.0 - 300.0, screen_height() - 40.0),
+                    Vec2::new(600.0, 25.0),
+                    |ui| {
+                        ui.slider(hash!(), "tick", 0.0..player.num_ticks() as f32, &mut scrub);
+                    },
+                );
+                if scrub as usize != player.tick() {
+                    player.seek(scrub as usize);
+                }
+            }
+            if root_ui().button(Some(Vec2::new(screen_width() - 100.0, 60.0)), "back") {
+                stage = replay_return;
+            }
+        }
+
+        if stage == Stage::History {
+            draw_text_at("history", 80.0, 200.0, 100, font.as_ref());
+            let runs: Vec<&Run> = profile.history.iter().rev().collect();
+            let pages = runs.len().div_ceil(HISTORY_PAGE).max(1);
+            history_page = history_page.min(pages - 1);
+            if runs.is_empty() {
+                draw_text_ul("no runs yet", 80.0, 260.0, font.as_ref());
+            }
+            for (i, run) in runs
+                .iter()
+                .skip(history_page * HISTORY_PAGE)
+                .take(HISTORY_PAGE)
+                .enumerate()
+            {
+                let accuracy = match run.breakdown.accuracy() {
+                    Some(accuracy) => format!("{:.0}%", 100.0 * accuracy),
+                    None => "-".to_owned(),
+                };
+                let text = format!(
+                    "{}  {:<14} {:<12} {:>10}  {:>4} kills  {:>4} accuracy  {:>6.1} s  seed {}",
+                    format_date(run.finished),
+                    run.map,
+                    run.mode.name(),
+                    run.mode.format_score(run.breakdown.score),
+                    run.breakdown.kills,
+                    accuracy,
+                    run.secs,
+                    run.seed
+                );
+                draw_score_at(&text, 80.0, 260.0 + 20.0 * i as f32, font.as_ref());
+            }
+            let buttons_y = 280.0 + 20.0 * HISTORY_PAGE as f32;
+            draw_score_at(
+                &format!("page {} of {}", history_page + 1, pages),
+                80.0,
+                buttons_y - 8.0,
+                font.as_ref(),
+            );
+            if history_page > 0 && root_ui().button(Some(Vec2::new(80.0, buttons_y)), "newer") {
+                history_page -= 1;
+            }
+            if history_page + 1 < pages
+                && root_ui().button(Some(Vec2::new(160.0, buttons_y)), "older")
+            {
+                history_page += 1;
+            }
+            if root_ui().button(Some(Vec2::new(240.0, buttons_y)), "back") {
+                stage = Stage::Home;
+            }
+        }
+
+        let settings = Settings {
+            map: Some(maps[current_map].name.clone()),
+            mode,
+            field_view: Some(field_view.name().to_owned()),
+        };
+        if settings != profile.settings {
+            profile.settings = settings;
+            save_profile(&profile, &profile_path);
         }
 
         next_frame().await
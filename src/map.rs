@@ -0,0 +1,467 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::bail;
+use macroquad::math::Vec2;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::enemy::{Behaviour, Enemy, EnemyType, MAX_SPLIT_GENERATIONS, MAX_SPLIT_PIECES};
+use crate::field::Field;
+use crate::obstacle::Obstacle;
+use crate::physics::Physics;
+use crate::scoring::ScoringRules;
+use crate::sim::{
+    Body, Boundary, ARENA, BAKE_MARGIN, ENEMY_INIT_SPEED, ENEMY_RADIUS, GAME_TIME_SECS, MAX_ENEMIES,
+};
+use crate::wave::Waves;
+use crate::weapon::{default_weapons, Weapon};
+
+pub const MAP_DIR: &str = "maps";
+/// Longest an arena can be each way, since the grids the game keeps over it
+/// grow with its area.
+pub const MAX_ARENA_SIDE: f32 = 16384.0;
+/// Most points a baked field grid can have.
+pub const MAX_BAKED_POINTS: f64 = (1 << 20) as f64;
+
+/// The maps the game ships with. These are always available, even without a
+/// `maps/` directory next to the game, and are the only ones with online
+/// leaderboards.
+const BUILTIN_MAPS: [&str; 8] = [
+    include_str!("../maps/dual-vision.json"),
+    include_str!("../maps/curl-valley.json"),
+    include_str!("../maps/clockback.json"),
+    include_str!("../maps/whirlpool.json"),
+    include_str!("../maps/open-sea.json"),
+    include_str!("../maps/rapids.json"),
+    include_str!("../maps/menagerie.json"),
+    include_str!("../maps/gauntlet.json"),
+];
+
+/// A map file. Everything but the name and the field can be left out.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Map {
+    pub name: String,
+    pub field: Field,
+    #[serde(default = "default_time_limit")]
+    pub time_limit: f32,
+    /// how big the arena is; anything bigger than the screen scrolls
+    #[serde(default = "default_size")]
+    pub size: [f32; 2],
+    #[serde(default = "Physics::player")]
+    pub player: Physics,
+    #[serde(default)]
+    pub enemies: EnemySettings,
+    /// a script of what enemies come in when, instead of keeping the arena
+    /// topped up to `enemies.max`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub waves: Option<Waves>,
+    /// give the player health and lives, and end the run when they're gone,
+    /// instead of taking points off for every collision
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub survival: Option<Survival>,
+    #[serde(default)]
+    pub scoring: ScoringRules,
+    #[serde(default)]
+    pub colors: Colors,
+    #[serde(default)]
+    pub boundaries: Boundaries,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub obstacles: Vec<Obstacle>,
+    /// what the player can fire, switched between with the number keys
+    #[serde(default = "default_weapons")]
+    pub weapons: Vec<Weapon>,
+    /// look the field up on a precomputed grid instead of evaluating it
+    /// everywhere it's needed, for fields that are slow to evaluate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bake: Option<BakeSettings>,
+}
+
+fn default_time_limit() -> f32 {
+    GAME_TIME_SECS
+}
+
+fn default_size() -> [f32; 2] {
+    ARENA.to_array()
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnemySettings {
+    /// how many enemies can be on screen at once
+    pub max: usize,
+    pub radius: f32,
+    /// how fast enemies are launched towards the player
+    pub speed: f32,
+    pub physics: Physics,
+    /// the kinds of enemies to spawn; left out, every enemy is a plain one
+    /// with the settings above
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub types: Vec<EnemyType>,
+}
+
+impl EnemySettings {
+    /// An enemy of type `kind`, or a plain one, at `pos` and launched
+    /// towards `target`.
+    pub fn launch(&self, kind: Option<&EnemyType>, pos: Vec2, target: Vec2) -> Enemy {
+        let dir = (target - pos).normalize_or(Vec2::Y);
+        match kind {
+            Some(kind) => Enemy::spawn(kind, pos, dir * kind.speed),
+            None => {
+                let body = Body::new(pos, dir * self.speed).with_physics(self.physics);
+                Enemy::new(body, self.radius)
+            }
+        }
+    }
+}
+
+impl Default for EnemySettings {
+    fn default() -> EnemySettings {
+        EnemySettings {
+            max: MAX_ENEMIES,
+            radius: ENEMY_RADIUS,
+            speed: ENEMY_INIT_SPEED,
+            physics: Physics::enemy(),
+            types: vec![],
+        }
+    }
+}
+
+/// What each kind of body does at the edge of the arena.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Boundaries {
+    pub player: Boundary,
+    pub projectiles: Boundary,
+    pub enemies: Boundary,
+}
+
+impl Default for Boundaries {
+    fn default() -> Boundaries {
+        Boundaries {
+            player: Boundary::Push,
+            projectiles: Boundary::Kill,
+            enemies: Boundary::Open,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BakeSettings {
+    /// how far apart the grid points are, in pixels
+    pub cell: f32,
+    /// for fields that change over time, how many seconds go by before the
+    /// grid is sampled again
+    pub slice: f32,
+}
+
+impl Default for BakeSettings {
+    fn default() -> BakeSettings {
+        BakeSettings {
+            cell: 10.0,
+            slice: 0.1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Survival {
+    /// hits the player can take before losing a life
+    pub health: u32,
+    pub lives: u32,
+    /// seconds after a hit before the player can be hit again
+    pub invulnerable: f32,
+    /// how fast a hit sends the player flying away from the enemy
+    pub knockback: f32,
+}
+
+impl Default for Survival {
+    fn default() -> Survival {
+        Survival {
+            health: 3,
+            lives: 1,
+            invulnerable: 1.5,
+            knockback: 800.0,
+        }
+    }
+}
+
+/// Colours as `0xRRGGBB`, written as `"#RRGGBB"` in map files.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Colors {
+    #[serde(with = "hex")]
+    pub background: u32,
+    #[serde(with = "hex")]
+    pub field: u32,
+    #[serde(with = "hex")]
+    pub player: u32,
+    #[serde(with = "hex")]
+    pub projectile: u32,
+    #[serde(with = "hex")]
+    pub enemy: u32,
+    #[serde(with = "hex")]
+    pub obstacle: u32,
+}
+
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors {
+            background: 0xFEFAE0,
+            field: 0xDDA15E,
+            player: 0x22577a,
+            projectile: 0xbc4749,
+            enemy: 0xBC6C25,
+            obstacle: 0x606C38,
+        }
+    }
+}
+
+pub(crate) mod hex {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(color: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("#{:06X}", color))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        u32::from_str_radix(text.trim_start_matches('#'), 16).map_err(serde::de::Error::custom)
+    }
+
+    /// For colours that fall back to another one when left out.
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            color: &Option<u32>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match color {
+                Some(color) => super::serialize(color, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<u32>, D::Error> {
+            super::deserialize(deserializer).map(Some)
+        }
+    }
+}
+
+impl Map {
+    /// A map with the default time limit, enemies and colours.
+    pub fn new(name: &str, field: Field) -> Map {
+        Map {
+            name: name.to_owned(),
+            field,
+            time_limit: default_time_limit(),
+            size: default_size(),
+            player: Physics::player(),
+            enemies: EnemySettings::default(),
+            waves: None,
+            survival: None,
+            scoring: ScoringRules::default(),
+            colors: Colors::default(),
+            boundaries: Boundaries::default(),
+            obstacles: vec![],
+            weapons: default_weapons(),
+            bake: None,
+        }
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<Map> {
+        let map: Map = serde_json::from_str(json)?;
+        map.check()?;
+        Ok(map)
+    }
+
+    /// Fails for settings the game can't play on at all, rather than letting
+    /// them crash it later.
+    pub fn check(&self) -> anyhow::Result<()> {
+        self.field.check()?;
+        if !self
+            .size
+            .iter()
+            .all(|&side| (1.0..=MAX_ARENA_SIDE).contains(&side))
+        {
+            bail!(
+                "the arena has to be from 1 to {} pixels each way, not {:?}",
+                MAX_ARENA_SIDE,
+                self.size
+            );
+        }
+        if let Some(waves) = &self.waves {
+            waves.check()?;
+        }
+        for kind in &self.enemies.types {
+            if let Behaviour::Splitter {
+                pieces,
+                generations,
+            } = kind.behaviour
+            {
+                if pieces > MAX_SPLIT_PIECES || generations > MAX_SPLIT_GENERATIONS {
+                    bail!(
+                        "splitters can break into at most {} pieces at most {} times, not {} pieces {} times",
+                        MAX_SPLIT_PIECES,
+                        MAX_SPLIT_GENERATIONS,
+                        pieces,
+                        generations
+                    );
+                }
+            }
+        }
+        if let Some(bake) = &self.bake {
+            if !(bake.cell > 0.0 && bake.cell.is_finite()) {
+                bail!(
+                    "a baked grid's cells have to be bigger than 0, not {}",
+                    bake.cell
+                );
+            }
+            // as many as `FieldGrid::bake` samples
+            let points: f64 = self
+                .size
+                .iter()
+                .map(|&side| ((side + 2.0 * BAKE_MARGIN) as f64 / bake.cell as f64).ceil() + 1.0)
+                .product();
+            if points > MAX_BAKED_POINTS {
+                bail!(
+                    "a baked grid can have at most {} points, not {} with cells {} pixels across",
+                    MAX_BAKED_POINTS,
+                    points,
+                    bake.cell
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn builtin_maps() -> Vec<Map> {
+    BUILTIN_MAPS
+        .iter()
+        .map(|json| Map::from_json(json).expect("built-in maps are valid"))
+        .collect()
+}
+
+/// The built-in maps plus every `.json` map in `dir`, in file name order. A
+/// map with the same name as an earlier one replaces it.
+pub fn load_maps(dir: &Path) -> Vec<Map> {
+    let mut maps = builtin_maps();
+    let mut paths: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .collect(),
+        Err(_) => vec![],
+    };
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+    paths.sort();
+    for path in paths {
+        match fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| Map::from_json(&json))
+        {
+            Ok(map) => match maps.iter_mut().find(|other| other.name == map.name) {
+                Some(other) => *other = map,
+                None => maps.push(map),
+            },
+            Err(err) => eprintln!("couldn't load map {}: {}", path.display(), err),
+        }
+    }
+    maps
+}
+
+pub fn find_map<'a>(maps: &'a [Map], name: &str) -> Option<&'a Map> {
+    maps.iter().find(|map| map.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mode::GameMode;
+    use crate::replay::Replay;
+
+    #[test]
+    fn builtin_maps_have_online_leaderboards() {
+        let schema = include_str!("../convex/schema.ts");
+        for map in builtin_maps() {
+            assert!(
+                schema.contains(&format!("{:?}", map.name)),
+                "{} is missing from the maps in convex/schema.ts",
+                map.name
+            );
+        }
+    }
+
+    fn with(extra: &str) -> anyhow::Result<Map> {
+        Map::from_json(&format!(
+            r#"{{ "name": "test", "field": {{ "type": "square", "scale": 0.01, "c": [0.0, 0.0] }}{} }}"#,
+            extra
+        ))
+    }
+
+    #[test]
+    fn checks_arena_size() {
+        assert!(with("").is_ok());
+        assert!(with(r#", "size": [1.0, 1.0]"#).is_ok());
+        assert!(with(r#", "size": [0.5, 900.0]"#).is_err());
+        assert!(with(r#", "size": [1600.0, 0.0]"#).is_err());
+        assert!(with(r#", "size": [-1600.0, 900.0]"#).is_err());
+        assert!(with(r#", "size": [1e40, 900.0]"#).is_err());
+        assert!(with(r#", "size": [16384.0, 16384.0]"#).is_ok());
+        assert!(with(r#", "size": [16385.0, 900.0]"#).is_err());
+        assert!(with(r#", "size": [1e12, 1e12]"#).is_err());
+
+        // replays that bring their own copy of the map are checked the same
+        let mut map = with("").unwrap();
+        map.size = [1e12, 1e12];
+        let replay = Replay::new(1, &map, GameMode::TimeAttack);
+        assert!(Replay::from_json(&replay.to_json()).is_err());
+    }
+
+    #[test]
+    fn checks_field() {
+        let map = |period: f32| {
+            Map::from_json(&format!(
+                r#"{{ "name": "test", "field": {{ "type": "pulse", "strength": 100.0, "period": {} }} }}"#,
+                period
+            ))
+        };
+        assert!(map(2.0).is_ok());
+        assert!(map(0.0).is_err());
+    }
+
+    #[test]
+    fn checks_splitters() {
+        let splitter = |pieces: u32, generations: u32| {
+            with(&format!(
+                r#", "enemies": {{ "types": [{{ "type": "splitter", "pieces": {}, "generations": {} }}] }}"#,
+                pieces, generations
+            ))
+        };
+        assert!(splitter(3, 2).is_ok());
+        assert!(splitter(MAX_SPLIT_PIECES, MAX_SPLIT_GENERATIONS).is_ok());
+        assert!(splitter(MAX_SPLIT_PIECES + 1, 1).is_err());
+        assert!(splitter(2, MAX_SPLIT_GENERATIONS + 1).is_err());
+        assert!(splitter(1000, 1000).is_err());
+    }
+
+    #[test]
+    fn checks_bake_cell() {
+        assert!(with(r#", "bake": {}"#).is_ok());
+        assert!(with(r#", "bake": { "cell": 0.0 }"#).is_err());
+        assert!(with(r#", "bake": { "cell": -10.0 }"#).is_err());
+        // a point every 2 pixels over the default arena is fine, every pixel
+        // is too many
+        assert!(with(r#", "bake": { "cell": 2.0 }"#).is_ok());
+        assert!(with(r#", "bake": { "cell": 1.0 }"#).is_err());
+        assert!(with(r#", "size": [16384.0, 16384.0], "bake": { "cell": 10.0 }"#).is_err());
+        assert!(with(r#", "size": [16384.0, 16384.0], "bake": { "cell": 20.0 }"#).is_ok());
+        let mut map = with(r#", "bake": { "cell": 10.0 }"#).unwrap();
+        map.bake.as_mut().unwrap().cell = f32::NAN;
+        assert!(map.check().is_err());
+    }
+}
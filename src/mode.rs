@@ -0,0 +1,222 @@
+use serde::{Deserialize, Serialize};
+
+use crate::map::{Map, Survival};
+use crate::sim::{Event, World, TICK_RATE};
+
+/// How many enemies a target run has to shoot.
+pub const TARGET_RUN_ENEMIES: i32 = 30;
+/// Seconds added to a target run's time for each collision, and for each
+/// enemy still to shoot if it runs out of time.
+pub const TARGET_RUN_PENALTY: f32 = 5.0;
+/// Longest a target run can go on for before it ends anyway.
+pub const TARGET_RUN_MAX_SECS: f32 = 300.0;
+/// Longest any run can go on for, even without a time limit, so checking a
+/// submitted one can't take forever.
+pub const MAX_RUN_SECS: f32 = 3600.0;
+
+/// The rules a run is played by: when it's over, what the HUD shows and how
+/// it's scored. Every map has a leaderboard for each.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum GameMode {
+    /// the map's own rules: as many points as possible before the time runs
+    /// out, or before dying on endless maps
+    #[default]
+    TimeAttack,
+    /// no time limit, and the player has health and lives even on maps that
+    /// don't give them any
+    Survival,
+    /// no shooting, just lasting as long as possible
+    Pacifist,
+    /// shooting `TARGET_RUN_ENEMIES` enemies as quickly as possible
+    TargetRun,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 4] = [
+        GameMode::TimeAttack,
+        GameMode::Survival,
+        GameMode::Pacifist,
+        GameMode::TargetRun,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::TimeAttack => "time attack",
+            GameMode::Survival => "survival",
+            GameMode::Pacifist => "pacifist",
+            GameMode::TargetRun => "target run",
+        }
+    }
+
+    pub fn next(self) -> GameMode {
+        let i = GameMode::ALL.iter().position(|&mode| mode == self);
+        GameMode::ALL[i.map_or(0, |i| (i + 1) % GameMode::ALL.len())]
+    }
+
+    /// Which leaderboard runs on `map` in this mode go on. Time attack has a
+    /// new one too, rather than the map's original board, which is full of
+    /// scores from before they were verified and scored differently.
+    pub fn leaderboard(self, map: &str) -> String {
+        format!("{} ({})", map, self.name())
+    }
+
+    /// Sorts scores best first. Target runs are timed, so lower is better.
+    pub fn rank(self, score: i32) -> i32 {
+        match self {
+            GameMode::TargetRun => score,
+            _ => -score,
+        }
+    }
+
+    pub fn can_fire(self) -> bool {
+        self != GameMode::Pacifist
+    }
+
+    /// Whether combos, streaks and curved shots count toward the score, which
+    /// they only do in modes scored on points.
+    pub fn scores_bonuses(self) -> bool {
+        matches!(self, GameMode::TimeAttack | GameMode::Survival)
+    }
+
+    /// How long a run has before it ends anyway, if it's timed at all.
+    pub fn time_limit(self, map: &Map) -> Option<f32> {
+        match self {
+            GameMode::TimeAttack => {
+                let endless = map.waves.as_ref().is_some_and(|waves| waves.endless);
+                (!endless).then_some(map.time_limit)
+            }
+            GameMode::TargetRun => Some(TARGET_RUN_MAX_SECS),
+            _ => None,
+        }
+    }
+
+    /// The most ticks a run on `map` can last before it's over, one way or
+    /// another.
+    pub fn max_ticks(self, map: &Map) -> u64 {
+        let secs = self
+            .time_limit(map)
+            .map_or(MAX_RUN_SECS, |secs| secs.min(MAX_RUN_SECS));
+        // with a little to spare, as the timer adds up ticks in floating
+        // point and drifts a bit over a long run
+        (1.01 * secs.max(0.0) * TICK_RATE).ceil() as u64 + 1
+    }
+
+    /// The health and lives the player has on `map`, if any; without them
+    /// every collision just counts against the score.
+    pub fn survival(self, map: &Map) -> Option<Survival> {
+        match self {
+            GameMode::Survival | GameMode::Pacifist => {
+                Some(map.survival.clone().unwrap_or_default())
+            }
+            _ => map.survival.clone(),
+        }
+    }
+
+    /// Why the run is over, if it is.
+    pub fn finished(self, world: &World) -> Option<Event> {
+        if world.is_dead() {
+            Some(Event::Died)
+        } else if self == GameMode::TargetRun && world.num_enemies_shot >= TARGET_RUN_ENEMIES {
+            Some(Event::Cleared)
+        } else if world.secs_left <= 0.0 || world.elapsed >= MAX_RUN_SECS {
+            Some(Event::TimeUp)
+        } else {
+            None
+        }
+    }
+
+    /// Time attack and survival are scored on points: what the enemies shot
+    /// were worth plus any bonuses, less a point for every shot. With the
+    /// bonuses that isn't how time attack was scored before, which is one
+    /// reason it has its own leaderboard rather than the map's original one.
+    pub fn score(self, world: &World) -> i32 {
+        match self {
+            GameMode::TimeAttack => {
+                // with health, collisions cost that instead
+                let penalty = match world.survival {
+                    Some(_) => 0,
+                    None => 1000 * world.num_collisions,
+                };
+                world.enemy_points + world.scoring.bonus() - world.num_projectiles - penalty
+            }
+            GameMode::Survival => {
+                world.enemy_points + world.scoring.bonus() - world.num_projectiles
+            }
+            GameMode::Pacifist => hundredths(world.elapsed),
+            GameMode::TargetRun => {
+                let left = (TARGET_RUN_ENEMIES - world.num_enemies_shot).max(0);
+                let penalties = world.num_collisions + left;
+                hundredths(world.elapsed + TARGET_RUN_PENALTY * penalties as f32)
+            }
+        }
+    }
+
+    /// Timed modes score in hundredths of a second.
+    pub fn format_score(self, score: i32) -> String {
+        match self {
+            GameMode::Pacifist | GameMode::TargetRun => format!("{:.2} s", score as f32 / 100.0),
+            _ => score.to_string(),
+        }
+    }
+
+    /// The clock in the corner of the HUD.
+    pub fn clock(self, world: &World) -> String {
+        match self {
+            GameMode::TimeAttack if !world.is_endless() => {
+                format!("time left {:.1} s", world.secs_left)
+            }
+            GameMode::TimeAttack | GameMode::Survival => format!("time {:.1} s", world.elapsed),
+            GameMode::Pacifist => format!("survived {:.1} s", world.elapsed),
+            GameMode::TargetRun => format!(
+                "{}/{} in {:.1} s",
+                world.num_enemies_shot, TARGET_RUN_ENEMIES, world.elapsed
+            ),
+        }
+    }
+
+    /// How the score was made up, a line at a time, for the end screen.
+    pub fn summary(self, world: &World) -> Vec<String> {
+        let shot = format!(
+            "enemies shot {} for {}",
+            world.num_enemies_shot, world.enemy_points
+        );
+        let projectiles = format!("projectiles used -1 x {}", world.num_projectiles);
+        let hits = format!("hits taken {}", world.num_collisions);
+        match self {
+            GameMode::TimeAttack => {
+                let collisions = match world.survival {
+                    Some(_) => hits,
+                    None => format!("enemy collisions -1000 x {}", world.num_collisions),
+                };
+                vec![shot, projectiles, collisions]
+            }
+            GameMode::Survival => vec![
+                shot,
+                projectiles,
+                format!("survived {:.1} s", world.elapsed),
+            ],
+            GameMode::Pacifist => vec![format!("survived {:.2} s", world.elapsed), hits],
+            GameMode::TargetRun => {
+                let left = (TARGET_RUN_ENEMIES - world.num_enemies_shot).max(0);
+                vec![
+                    format!(
+                        "enemies shot {}/{} in {:.2} s",
+                        world.num_enemies_shot, TARGET_RUN_ENEMIES, world.elapsed
+                    ),
+                    format!(
+                        "enemy collisions +{} s x {}",
+                        TARGET_RUN_PENALTY, world.num_collisions
+                    ),
+                    format!("enemies left +{} s x {}", TARGET_RUN_PENALTY, left),
+                ]
+            }
+        }
+    }
+}
+
+fn hundredths(secs: f32) -> i32 {
+    (secs * 100.0).round() as i32
+}
@@ -0,0 +1,271 @@
+use macroquad::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// Something solid in the arena, in arena coordinates. With `deflect` set,
+/// the field flows around it instead of straight through.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Obstacle {
+    #[serde(flatten)]
+    pub shape: Shape,
+    #[serde(default)]
+    pub deflect: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Shape {
+    Circle {
+        center: [f32; 2],
+        radius: f32,
+    },
+    /// convex, with the points in order around the outside
+    Polygon {
+        points: Vec<[f32; 2]>,
+    },
+    Wall {
+        from: [f32; 2],
+        to: [f32; 2],
+        #[serde(default = "default_thickness")]
+        thickness: f32,
+    },
+}
+
+fn default_thickness() -> f32 {
+    20.0
+}
+
+impl Obstacle {
+    /// If a body at `pos` with `radius` overlaps the obstacle, where it has to
+    /// move to so it just touches it, and the direction away from the
+    /// obstacle there.
+    pub fn collide(&self, pos: Vec2, radius: f32) -> Option<(Vec2, Vec2)> {
+        match &self.shape {
+            Shape::Circle {
+                center,
+                radius: size,
+            } => {
+                let center = Vec2::from_array(*center);
+                let away = pos - center;
+                if away.length() >= size + radius {
+                    return None;
+                }
+                let normal = away.normalize_or(Vec2::NEG_Y);
+                Some((center + (size + radius) * normal, normal))
+            }
+            Shape::Polygon { points } => {
+                let closest = points
+                    .iter()
+                    .zip(points.iter().cycle().skip(1))
+                    .map(|(a, b)| closest_on_segment(pos, (*a).into(), (*b).into()))
+                    .min_by(|a, b| a.distance_squared(pos).total_cmp(&b.distance_squared(pos)))?;
+                let normal = if contains(points, pos) {
+                    (closest - pos).normalize_or(Vec2::NEG_Y)
+                } else if closest.distance(pos) < radius {
+                    (pos - closest).normalize_or(Vec2::NEG_Y)
+                } else {
+                    return None;
+                };
+                Some((closest + radius * normal, normal))
+            }
+            Shape::Wall {
+                from,
+                to,
+                thickness,
+            } => {
+                let (from, to) = (Vec2::from_array(*from), Vec2::from_array(*to));
+                let closest = closest_on_segment(pos, from, to);
+                let reach = thickness / 2.0 + radius;
+                if closest.distance(pos) >= reach {
+                    return None;
+                }
+                let normal = (pos - closest).normalize_or((to - from).perp().normalize_or(Vec2::Y));
+                Some((closest + reach * normal, normal))
+            }
+        }
+    }
+
+    /// Circles covering the obstacle, for deflecting the field around it.
+    /// Exact for circles; polygons get the circle around their points, and
+    /// walls a row of circles as wide as the wall.
+    pub fn deflector_circles(&self) -> Vec<(Vec2, f32)> {
+        match &self.shape {
+            Shape::Circle { center, radius } => vec![((*center).into(), *radius)],
+            Shape::Polygon { points } => {
+                let points: Vec<Vec2> = points.iter().map(|point| (*point).into()).collect();
+                let center = points.iter().sum::<Vec2>() / points.len().max(1) as f32;
+                let radius = points
+                    .iter()
+                    .map(|point| point.distance(center))
+                    .fold(0.0, f32::max);
+                vec![(center, radius)]
+            }
+            Shape::Wall {
+                from,
+                to,
+                thickness,
+            } => {
+                let (from, to) = (Vec2::from_array(*from), Vec2::from_array(*to));
+                let count = (from.distance(to) / thickness).ceil() as usize + 1;
+                (0..count)
+                    .map(|i| {
+                        let t = i as f32 / (count - 1).max(1) as f32;
+                        (from.lerp(to, t), thickness / 2.0)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+fn closest_on_segment(pos: Vec2, a: Vec2, b: Vec2) -> Vec2 {
+    let along = b - a;
+    let t = (pos - a).dot(along) / along.length_squared().max(f32::EPSILON);
+    a + t.clamp(0.0, 1.0) * along
+}
+
+fn contains(points: &[[f32; 2]], pos: Vec2) -> bool {
+    let mut inside = false;
+    for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+        let (a, b) = (Vec2::from_array(*a), Vec2::from_array(*b));
+        if (a.y > pos.y) != (b.y > pos.y) && pos.x < a.x + (pos.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// The flow around a circle in potential flow, for a field that would
+/// otherwise be `stream` all around it: what has to be added to the field at
+/// `pos` so that nothing flows into the circle. Falls off with the square of
+/// the distance.
+#[derive(Clone, Copy, Debug)]
+pub struct Doublet {
+    pub center: Vec2,
+    pub radius: f32,
+    pub stream: Vec2,
+}
+
+impl Doublet {
+    pub fn deflection(&self, pos: Vec2) -> Vec2 {
+        // -R² conj(U) / conj(z)², treating vectors as complex numbers
+        let z = pos - self.center;
+        let dist_squared = z.length_squared();
+        if dist_squared <= self.radius * self.radius {
+            return -self.stream;
+        }
+        let conj_stream = Vec2::new(self.stream.x, -self.stream.y);
+        -self.radius * self.radius * conj_stream.rotate(z.rotate(z)) / (dist_squared * dist_squared)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use super::*;
+
+    fn obstacle(shape: Shape) -> Obstacle {
+        Obstacle {
+            shape,
+            deflect: false,
+        }
+    }
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!(a.distance(b) < 1e-3, "{} is not {}", a, b);
+    }
+
+    fn assert_pushed(obstacle: &Obstacle, pos: Vec2, radius: f32, to: Vec2, normal: Vec2) {
+        let (pushed, pushed_normal) = obstacle.collide(pos, radius).expect("overlaps");
+        assert_near(pushed, to);
+        assert_near(pushed_normal, normal);
+    }
+
+    #[test]
+    fn pushes_out_of_circles() {
+        let circle = obstacle(Shape::Circle {
+            center: [100.0, 100.0],
+            radius: 20.0,
+        });
+        let v = Vec2::new;
+        assert_pushed(&circle, v(110.0, 100.0), 5.0, v(125.0, 100.0), Vec2::X);
+        assert_pushed(&circle, v(100.0, 120.0), 5.0, v(100.0, 125.0), Vec2::Y);
+        // right in the middle there's no way out that's better than another
+        assert_pushed(&circle, v(100.0, 100.0), 5.0, v(100.0, 75.0), Vec2::NEG_Y);
+        assert!(circle.collide(v(126.0, 100.0), 5.0).is_none());
+    }
+
+    #[test]
+    fn pushes_out_of_polygons() {
+        let square = obstacle(Shape::Polygon {
+            points: vec![[0.0, 0.0], [100.0, 0.0], [100.0, 100.0], [0.0, 100.0]],
+        });
+        let v = Vec2::new;
+        // from inside, out through the nearest side
+        assert_pushed(&square, v(50.0, 10.0), 5.0, v(50.0, -5.0), Vec2::NEG_Y);
+        assert_pushed(&square, v(95.0, 50.0), 5.0, v(105.0, 50.0), Vec2::X);
+        // and from just outside
+        assert_pushed(&square, v(50.0, 103.0), 5.0, v(50.0, 105.0), Vec2::Y);
+        let corner = v(1.0, 1.0).normalize();
+        assert_pushed(
+            &square,
+            v(102.0, 102.0),
+            5.0,
+            v(100.0, 100.0) + 5.0 * corner,
+            corner,
+        );
+        assert!(square.collide(v(50.0, 106.0), 5.0).is_none());
+        assert!(square.collide(v(104.0, 104.0), 5.0).is_none());
+    }
+
+    #[test]
+    fn pushes_out_of_walls() {
+        let wall = obstacle(Shape::Wall {
+            from: [0.0, 0.0],
+            to: [100.0, 0.0],
+            thickness: 20.0,
+        });
+        let v = Vec2::new;
+        assert_pushed(&wall, v(50.0, 5.0), 5.0, v(50.0, 15.0), Vec2::Y);
+        assert_pushed(&wall, v(50.0, -5.0), 5.0, v(50.0, -15.0), Vec2::NEG_Y);
+        // right on the line, off to one side
+        assert_pushed(&wall, v(50.0, 0.0), 5.0, v(50.0, 15.0), Vec2::Y);
+        // round the ends
+        assert_pushed(&wall, v(110.0, 0.0), 5.0, v(115.0, 0.0), Vec2::X);
+        assert!(wall.collide(v(50.0, 15.0), 5.0).is_none());
+        assert!(wall.collide(v(116.0, 0.0), 5.0).is_none());
+    }
+
+    #[test]
+    fn nothing_flows_into_a_deflecting_circle() {
+        let doublet = Doublet {
+            center: Vec2::new(200.0, 100.0),
+            radius: 50.0,
+            stream: Vec2::new(30.0, -40.0),
+        };
+        for i in 0..16 {
+            let normal = Vec2::from_angle(TAU * i as f32 / 16.0);
+            let pos = doublet.center + 50.001 * normal;
+            let flow = doublet.stream + doublet.deflection(pos);
+            assert!(
+                flow.dot(normal).abs() < 0.01,
+                "{} flows in at {}",
+                flow,
+                pos
+            );
+        }
+        // it all goes round the sides, twice as fast as elsewhere
+        let side = doublet.center + 50.001 * doublet.stream.perp().normalize();
+        let flow = doublet.stream + doublet.deflection(side);
+        assert!(flow.distance(2.0 * doublet.stream) < 0.01, "{}", flow);
+        // and far away the field is left as it is
+        let far = doublet.center + Vec2::new(1e5, 0.0);
+        assert!(doublet.deflection(far).length() < 1e-3);
+        // inside, there's no flow at all
+        assert_eq!(
+            doublet.stream + doublet.deflection(doublet.center),
+            Vec2::ZERO
+        );
+    }
+}
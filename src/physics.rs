@@ -0,0 +1,69 @@
+use macroquad::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::sim::{ENEMY_INERTIA, FRICTION, PLAYER_MAX_MOVEMENT_SPEED, PROJECTILE_INERTIA};
+
+/// How a body responds to being pushed, by the field or by itself. Anything
+/// left out of a map file is a plain unit mass that goes with the field.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Physics {
+    /// divides every force on the body
+    pub mass: f32,
+    /// how much speed the body loses per second, as a fraction of its speed
+    pub drag: f32,
+    /// how strongly the field pushes the body
+    pub coupling: f32,
+    /// the body is never faster than this
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_speed: Option<f32>,
+}
+
+impl Default for Physics {
+    fn default() -> Physics {
+        Physics {
+            mass: 1.0,
+            drag: 0.0,
+            coupling: 1.0,
+            max_speed: None,
+        }
+    }
+}
+
+impl Physics {
+    /// Feels the whole field, and slows down to a top speed when moving
+    /// under its own power.
+    pub fn player() -> Physics {
+        Physics {
+            drag: FRICTION / PLAYER_MAX_MOVEMENT_SPEED,
+            ..Physics::default()
+        }
+    }
+
+    pub fn projectile() -> Physics {
+        Physics {
+            coupling: PROJECTILE_INERTIA,
+            ..Physics::default()
+        }
+    }
+
+    pub fn enemy() -> Physics {
+        Physics {
+            coupling: ENEMY_INERTIA,
+            ..Physics::default()
+        }
+    }
+
+    /// The acceleration of a body moving at `vel` with `field` being the
+    /// field where it is, while pushing itself with `thrust`.
+    pub fn accel(&self, thrust: Vec2, field: Vec2, vel: Vec2) -> Vec2 {
+        (thrust + self.coupling * field) / self.mass - self.drag * vel
+    }
+
+    pub fn limit(&self, vel: Vec2) -> Vec2 {
+        match self.max_speed {
+            Some(max_speed) => vel.clamp_length_max(max_speed),
+            None => vel,
+        }
+    }
+}
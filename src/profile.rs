@@ -0,0 +1,315 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::mode::GameMode;
+use crate::scoring::Breakdown;
+
+/// Bump whenever the profile's shape changes, and add a step to `MIGRATIONS`
+/// that brings the old shape up to date.
+pub const PROFILE_VERSION: u32 = 1;
+
+const PROFILE_FILE: &str = "profile.json";
+
+/// Upgrades a profile saved at version `i + 1` to version `i + 2`.
+const MIGRATIONS: &[fn(&mut Value)] = &[];
+// every version but the first needs a way to get to it
+const _: () = assert!(MIGRATIONS.len() == PROFILE_VERSION as usize - 1);
+
+/// Everything the game remembers between launches.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Profile {
+    pub version: u32,
+    /// the best score on each map in each mode
+    #[serde(default)]
+    pub bests: BTreeMap<String, BTreeMap<GameMode, i32>>,
+    /// every run played, oldest first
+    #[serde(default)]
+    pub history: Vec<Run>,
+    /// what was last typed in for submitting a score
+    #[serde(default)]
+    pub initials: String,
+    #[serde(default)]
+    pub settings: Settings,
+}
+
+impl Default for Profile {
+    fn default() -> Profile {
+        Profile {
+            version: PROFILE_VERSION,
+            bests: BTreeMap::new(),
+            history: vec![],
+            initials: String::new(),
+            settings: Settings::default(),
+        }
+    }
+}
+
+/// A finished run.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Run {
+    pub map: String,
+    pub mode: GameMode,
+    pub seed: u64,
+    /// seconds since the Unix epoch when it ended
+    pub finished: f64,
+    /// how long it lasted, in seconds
+    pub secs: f32,
+    pub breakdown: Breakdown,
+}
+
+/// The choices on the home screen, as they were last left.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub map: Option<String>,
+    pub mode: GameMode,
+    /// the name of the way the field is drawn
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field_view: Option<String>,
+}
+
+/// Where the profile is kept: `FLOWFIELD_DATA` if it's set, and otherwise
+/// the usual place for each platform's app data.
+pub fn data_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("FLOWFIELD_DATA") {
+        return dir.into();
+    }
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let dir = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home.map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| home.join(".local").join("share")))
+    };
+    dir.map_or_else(|| PathBuf::from("."), |dir| dir.join("flowfield"))
+}
+
+impl Profile {
+    pub fn path() -> PathBuf {
+        data_dir().join(PROFILE_FILE)
+    }
+
+    /// Reads a profile saved by this or any older version of the game.
+    pub fn load(path: &Path) -> anyhow::Result<Profile> {
+        let mut profile: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let version = profile["version"]
+            .as_u64()
+            .ok_or_else(|| anyhow!("profile has no version"))? as u32;
+        if version == 0 || version > PROFILE_VERSION {
+            bail!(
+                "profile version {} is not supported (expected up to {})",
+                version,
+                PROFILE_VERSION
+            );
+        }
+        for migrate in &MIGRATIONS[version as usize - 1..] {
+            migrate(&mut profile);
+        }
+        profile["version"] = PROFILE_VERSION.into();
+        Ok(serde_json::from_value(profile)?)
+    }
+
+    /// The saved profile, or a fresh one if there isn't one yet. One that
+    /// can't be read is moved aside rather than overwritten.
+    pub fn load_or_default(path: &Path) -> Profile {
+        if !path.exists() {
+            return Profile::default();
+        }
+        Profile::load(path).unwrap_or_else(|err| {
+            let backup = path.with_extension("json.bak");
+            eprintln!(
+                "couldn't load profile {} ({}), moving it to {}",
+                path.display(),
+                err,
+                backup.display()
+            );
+            let _ = fs::rename(path, backup);
+            Profile::default()
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // write it all out before replacing the old one, so a crash halfway
+        // through can't lose everything
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(temp, path)?;
+        Ok(())
+    }
+
+    pub fn best(&self, map: &str, mode: GameMode) -> Option<i32> {
+        self.bests.get(map)?.get(&mode).copied()
+    }
+
+    /// Adds the run to the history, and returns whether it's a new best.
+    pub fn record(&mut self, run: Run) -> bool {
+        let score = run.breakdown.score;
+        let best = self.best(&run.map, run.mode);
+        let is_best = best.is_none_or(|best| run.mode.rank(score) < run.mode.rank(best));
+        if is_best {
+            self.bests
+                .entry(run.map.clone())
+                .or_default()
+                .insert(run.mode, score);
+        }
+        self.history.push(run);
+        is_best
+    }
+}
+
+/// `secs` since the Unix epoch as a UTC date and time, e.g.
+/// `2024-07-21 14:31`.
+pub fn format_date(secs: f64) -> String {
+    let secs = secs.max(0.0) as i64;
+    let (days, time) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    // days to a civil date, from Howard Hinnant's `civil_from_days`
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A path in a fresh directory of its own, for each test.
+    fn temp_path(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("flowfield-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join(PROFILE_FILE)
+    }
+
+    fn run(map: &str, mode: GameMode, score: i32) -> Run {
+        Run {
+            map: map.to_owned(),
+            mode,
+            seed: 42,
+            finished: 1721572260.0,
+            secs: 30.0,
+            breakdown: Breakdown {
+                kills: 0,
+                enemy_points: 0,
+                combo_bonus: 0,
+                curve_bonus: 0,
+                streak_bonus: 0,
+                projectiles: 0,
+                hits: 0,
+                collisions: 0,
+                best_streak: 0,
+                best_combo: 0,
+                score,
+            },
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = temp_path("save-and-load");
+        let mut profile = Profile::default();
+        profile.record(run("clockback", GameMode::Survival, 1200));
+        profile.initials = "ab".to_owned();
+        profile.settings.map = Some("clockback".to_owned());
+        profile.settings.mode = GameMode::Survival;
+        profile.save(&path).unwrap();
+
+        let loaded = Profile::load(&path).unwrap();
+        assert_eq!(loaded.best("clockback", GameMode::Survival), Some(1200));
+        assert_eq!(loaded.history.len(), 1);
+        assert_eq!(loaded.history[0].breakdown, profile.history[0].breakdown);
+        assert_eq!(loaded.initials, "ab");
+        assert_eq!(loaded.settings, profile.settings);
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn fills_in_what_older_profiles_leave_out() {
+        let path = temp_path("fills-in");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, r#"{ "version": 1 }"#).unwrap();
+        let profile = Profile::load(&path).unwrap();
+        assert_eq!(profile.version, PROFILE_VERSION);
+        assert!(profile.bests.is_empty() && profile.history.is_empty());
+        assert_eq!(profile.settings, Settings::default());
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let path = temp_path("versions");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        for json in [
+            format!(r#"{{ "version": {} }}"#, PROFILE_VERSION + 1),
+            r#"{ "version": 0 }"#.to_owned(),
+            r#"{ "bests": {} }"#.to_owned(),
+        ] {
+            fs::write(&path, json).unwrap();
+            assert!(Profile::load(&path).is_err());
+        }
+    }
+
+    #[test]
+    fn moves_unreadable_profiles_aside() {
+        let path = temp_path("unreadable");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "{ not json").unwrap();
+        assert!(Profile::load(&path).is_err());
+
+        let profile = Profile::load_or_default(&path);
+        assert!(profile.history.is_empty());
+        assert!(!path.exists());
+        let backup = path.with_extension("json.bak");
+        assert_eq!(fs::read_to_string(backup).unwrap(), "{ not json");
+
+        // and starts afresh without one at all
+        assert!(Profile::load_or_default(&path).history.is_empty());
+    }
+
+    #[test]
+    fn keeps_the_best_score_for_each_mode() {
+        let mut profile = Profile::default();
+        assert!(profile.record(run("rapids", GameMode::TimeAttack, 500)));
+        assert!(!profile.record(run("rapids", GameMode::TimeAttack, 400)));
+        assert!(profile.record(run("rapids", GameMode::TimeAttack, 600)));
+        // target runs are timed, so lower is better
+        assert!(profile.record(run("rapids", GameMode::TargetRun, 3000)));
+        assert!(profile.record(run("rapids", GameMode::TargetRun, 2500)));
+        assert!(!profile.record(run("rapids", GameMode::TargetRun, 2600)));
+        assert_eq!(profile.best("rapids", GameMode::TimeAttack), Some(600));
+        assert_eq!(profile.best("rapids", GameMode::TargetRun), Some(2500));
+        assert_eq!(profile.best("rapids", GameMode::Pacifist), None);
+        assert_eq!(profile.history.len(), 6);
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(format_date(0.0), "1970-01-01 00:00");
+        assert_eq!(format_date(1721572260.0), "2024-07-21 14:31");
+        assert_eq!(format_date(951782400.0), "2000-02-29 00:00");
+    }
+}
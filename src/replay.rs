@@ -0,0 +1,344 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail};
+use base64::prelude::*;
+use macroquad::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::map::{find_map, Map};
+use crate::mode::GameMode;
+use crate::sim::{Event, Input, World, TICK_DT, TICK_RATE};
+
+/// Bump whenever the file format or the simulation changes in a way that
+/// would make older replays play back differently.
+pub const REPLAY_VERSION: u32 = 8;
+
+/// How often the player keeps a copy of the world to scrub back to.
+const KEYFRAME_TICKS: usize = 120;
+
+const UP: u8 = 1 << 0;
+const DOWN: u8 = 1 << 1;
+const LEFT: u8 = 1 << 2;
+const RIGHT: u8 = 1 << 3;
+const FIRE: u8 = 1 << 4;
+/// set when the run switches weapon, which is stored after the keys
+const SWITCH: u8 = 1 << 5;
+
+/// The same input held for a number of consecutive ticks.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputRun {
+    pub ticks: u32,
+    /// WASD and the mouse button packed into bits
+    pub keys: u8,
+    pub aim: [f32; 2],
+    /// the weapon switched to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weapon: Option<u8>,
+}
+
+impl InputRun {
+    fn new(input: &Input) -> InputRun {
+        let mut keys = 0;
+        for (held, bit) in [
+            (input.up, UP),
+            (input.down, DOWN),
+            (input.left, LEFT),
+            (input.right, RIGHT),
+            (input.fire, FIRE),
+        ] {
+            if held {
+                keys |= bit;
+            }
+        }
+        InputRun {
+            ticks: 1,
+            keys,
+            // aim only matters when firing, and dropping it otherwise lets
+            // long stretches of movement share a single run
+            aim: if input.fire {
+                input.aim.to_array()
+            } else {
+                [0.0; 2]
+            },
+            weapon: input.weapon.map(|weapon| weapon as u8),
+        }
+    }
+
+    fn input(&self) -> Input {
+        Input {
+            up: self.keys & UP != 0,
+            down: self.keys & DOWN != 0,
+            left: self.keys & LEFT != 0,
+            right: self.keys & RIGHT != 0,
+            aim: Vec2::from_array(self.aim),
+            fire: self.keys & FIRE != 0,
+            weapon: self.weapon.map(usize::from),
+        }
+    }
+}
+
+/// Everything needed to play a run back exactly: how the world was set up
+/// and the input for every tick.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub map: String,
+    #[serde(default)]
+    pub mode: GameMode,
+    /// the whole map, so a saved replay still plays back after the map file
+    /// is edited or removed, or for a custom field. Left out of compact
+    /// replays, which are only ever checked against the built-in maps.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub map_def: Option<Map>,
+    pub tick_rate: f32,
+    pub inputs: Vec<InputRun>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, map: &Map, mode: GameMode) -> Replay {
+        Replay {
+            version: REPLAY_VERSION,
+            seed,
+            map: map.name.clone(),
+            mode,
+            map_def: Some(map.clone()),
+            tick_rate: TICK_RATE,
+            inputs: vec![],
+        }
+    }
+
+    /// The world as it was at the start of the run, on the replay's own copy
+    /// of the map if it has one and otherwise the one out of `maps` with the
+    /// same name.
+    pub fn world(&self, maps: &[Map]) -> anyhow::Result<World> {
+        let map = match &self.map_def {
+            Some(map) => map,
+            None => find_map(maps, &self.map).ok_or_else(|| anyhow!("unknown map {}", self.map))?,
+        };
+        let mut world = World::new(map.clone(), self.seed);
+        world.mode = self.mode;
+        world.start();
+        Ok(world)
+    }
+
+    pub fn push(&mut self, input: &Input) {
+        let run = InputRun::new(input);
+        match self.inputs.last_mut() {
+            Some(last)
+                if last.keys == run.keys && last.aim == run.aim && last.weapon == run.weapon =>
+            {
+                last.ticks += 1
+            }
+            _ => self.inputs.push(run),
+        }
+    }
+
+    pub fn num_ticks(&self) -> u64 {
+        self.inputs.iter().map(|run| u64::from(run.ticks)).sum()
+    }
+
+    /// One input per tick.
+    pub fn inputs(&self) -> impl Iterator<Item = Input> + '_ {
+        self.inputs
+            .iter()
+            .flat_map(|run| std::iter::repeat_n(run.input(), run.ticks as usize))
+    }
+
+    fn check(self) -> anyhow::Result<Replay> {
+        if self.version != REPLAY_VERSION {
+            bail!(
+                "replay version {} is not supported (expected {})",
+                self.version,
+                REPLAY_VERSION
+            );
+        }
+        if self.tick_rate != TICK_RATE {
+            bail!("replay was recorded at {} ticks per second", self.tick_rate);
+        }
+        if let Some(map) = &self.map_def {
+            map.check()?;
+        }
+        Ok(self)
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<Replay> {
+        serde_json::from_str::<Replay>(json)?.check()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("replays are always serializable")
+    }
+
+    /// Packs the replay into a short base64 string, for sending along with a
+    /// score. Aim is only stored for runs where the player is firing.
+    pub fn to_compact(&self) -> String {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&self.tick_rate.to_le_bytes());
+        bytes.extend_from_slice(&(self.map.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(self.map.as_bytes());
+        let mode = GameMode::ALL.iter().position(|&mode| mode == self.mode);
+        bytes.push(mode.unwrap_or(0) as u8);
+        bytes.extend_from_slice(&(self.inputs.len() as u32).to_le_bytes());
+        for run in &self.inputs {
+            bytes.extend_from_slice(&run.ticks.to_le_bytes());
+            let switch = if run.weapon.is_some() { SWITCH } else { 0 };
+            bytes.push(run.keys | switch);
+            if run.keys & FIRE != 0 {
+                bytes.extend_from_slice(&run.aim[0].to_le_bytes());
+                bytes.extend_from_slice(&run.aim[1].to_le_bytes());
+            }
+            if let Some(weapon) = run.weapon {
+                bytes.push(weapon);
+            }
+        }
+        BASE64_STANDARD.encode(bytes)
+    }
+
+    /// Unpacks a replay packed by `to_compact`, for one of `maps`. Anything
+    /// longer than a run on that map in its mode could go on for is turned
+    /// away before it's played.
+    pub fn from_compact(compact: &str, maps: &[Map]) -> anyhow::Result<Replay> {
+        let bytes = BASE64_STANDARD.decode(compact)?;
+        let mut bytes = bytes.as_slice();
+        let version = u32::from_le_bytes(take(&mut bytes)?);
+        let seed = u64::from_le_bytes(take(&mut bytes)?);
+        let tick_rate = f32::from_le_bytes(take(&mut bytes)?);
+        let map_len = u16::from_le_bytes(take(&mut bytes)?) as usize;
+        if bytes.len() < map_len {
+            bail!("replay is truncated");
+        }
+        let (map, mut bytes) = bytes.split_at(map_len);
+        let map = String::from_utf8(map.to_vec())?;
+        let [mode] = take(&mut bytes)?;
+        let mode = *GameMode::ALL
+            .get(mode as usize)
+            .ok_or_else(|| anyhow!("unknown game mode {}", mode))?;
+        let max_ticks = find_map(maps, &map)
+            .ok_or_else(|| anyhow!("unknown map {}", map))
+            .map(|map| mode.max_ticks(map))?;
+        let num_runs = u32::from_le_bytes(take(&mut bytes)?);
+        let mut inputs = vec![];
+        let mut num_ticks = 0;
+        for _ in 0..num_runs {
+            let ticks = u32::from_le_bytes(take(&mut bytes)?);
+            num_ticks += u64::from(ticks);
+            if num_ticks > max_ticks {
+                bail!("replay is longer than a run on {} can be", map);
+            }
+            let [keys] = take(&mut bytes)?;
+            let aim = if keys & FIRE != 0 {
+                [
+                    f32::from_le_bytes(take(&mut bytes)?),
+                    f32::from_le_bytes(take(&mut bytes)?),
+                ]
+            } else {
+                [0.0; 2]
+            };
+            let weapon = match keys & SWITCH {
+                0 => None,
+                _ => Some(take::<1>(&mut bytes)?[0]),
+            };
+            inputs.push(InputRun {
+                ticks,
+                keys: keys & !SWITCH,
+                aim,
+                weapon,
+            });
+        }
+        if !bytes.is_empty() {
+            bail!("replay has {} trailing bytes", bytes.len());
+        }
+        Replay {
+            version,
+            seed,
+            map,
+            mode,
+            map_def: None,
+            tick_rate,
+            inputs,
+        }
+        .check()
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Replay> {
+        Replay::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_json())?;
+        Ok(())
+    }
+}
+
+fn take<const N: usize>(bytes: &mut &[u8]) -> anyhow::Result<[u8; N]> {
+    if bytes.len() < N {
+        bail!("replay is truncated");
+    }
+    let (head, rest) = bytes.split_at(N);
+    *bytes = rest;
+    Ok(head.try_into()?)
+}
+
+/// Plays a replay back through the simulation, one tick at a time, and can
+/// jump to any tick.
+pub struct ReplayPlayer {
+    pub world: World,
+    inputs: Vec<Input>,
+    tick: usize,
+    keyframes: Vec<World>,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: &Replay, maps: &[Map]) -> anyhow::Result<ReplayPlayer> {
+        let world = replay.world(maps)?;
+        Ok(ReplayPlayer {
+            keyframes: vec![world.clone()],
+            world,
+            inputs: replay.inputs().collect(),
+            tick: 0,
+        })
+    }
+
+    pub fn tick(&self) -> usize {
+        self.tick
+    }
+
+    pub fn num_ticks(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tick >= self.inputs.len()
+    }
+
+    pub fn step(&mut self) -> Vec<Event> {
+        if self.is_finished() {
+            return vec![];
+        }
+        if self.tick == self.keyframes.len() * KEYFRAME_TICKS {
+            self.keyframes.push(self.world.clone());
+        }
+        let events = self.world.step(TICK_DT, &self.inputs[self.tick]);
+        self.tick += 1;
+        events
+    }
+
+    pub fn seek(&mut self, tick: usize) {
+        let tick = tick.min(self.num_ticks());
+        let keyframe = (tick / KEYFRAME_TICKS).min(self.keyframes.len() - 1);
+        if tick < self.tick || self.tick < keyframe * KEYFRAME_TICKS {
+            self.world = self.keyframes[keyframe].clone();
+            self.tick = keyframe * KEYFRAME_TICKS;
+        }
+        while self.tick < tick {
+            self.step();
+        }
+    }
+}
@@ -0,0 +1,28 @@
+/// Same PCG generator as `macroquad::rand`, but owned by whoever needs it
+/// instead of living in a global, so a run can be reproduced from its seed.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+const DEFAULT_INC: u64 = 1442695040888963407;
+const MULTIPLIER: u64 = 6364136223846793005;
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        let mut rng = Rng { state: 0 };
+        rng.rand();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.rand();
+        rng
+    }
+
+    /// returns a pseudo-random number in the range of 0 to u32::MAX.
+    pub fn rand(&mut self) -> u32 {
+        let oldstate = self.state;
+        self.state = oldstate.wrapping_mul(MULTIPLIER).wrapping_add(DEFAULT_INC);
+        let xorshifted = (((oldstate >> 18) ^ oldstate) >> 27) as u32;
+        let rot = (oldstate >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+}
@@ -0,0 +1,297 @@
+use serde::{Deserialize, Serialize};
+
+use crate::sim::World;
+
+/// How a map rewards shooting well on top of what each enemy is worth.
+/// Setting a bonus to 0 turns it off.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScoringRules {
+    /// seconds after a kill that the next one keeps the combo going
+    pub combo_window: f32,
+    /// how much the multiplier on an enemy's points goes up with each kill
+    /// in a combo
+    pub combo_step: f32,
+    pub max_multiplier: f32,
+    /// kills in a row without getting hit for each streak bonus
+    pub streak_length: u32,
+    pub streak_bonus: i32,
+    /// degrees a shot has to have been turned by the field before its kill
+    /// earns a curve bonus
+    pub curve_min: f32,
+    /// the most turning a kill's curve bonus counts, so shots that circle
+    /// for a while before hitting something aren't worth a fortune
+    pub curve_max: f32,
+    /// points for a kill by a shot the field turned through 90 degrees, and
+    /// so on in proportion
+    pub curve_bonus: f32,
+}
+
+impl Default for ScoringRules {
+    fn default() -> ScoringRules {
+        ScoringRules {
+            combo_window: 1.0,
+            combo_step: 0.5,
+            max_multiplier: 3.0,
+            streak_length: 10,
+            streak_bonus: 500,
+            curve_min: 45.0,
+            curve_max: 180.0,
+            curve_bonus: 50.0,
+        }
+    }
+}
+
+/// Everything about how a run is going that the score is made of, beyond
+/// the counts the world keeps anyway.
+#[derive(Clone, Debug, Default)]
+pub struct Scoring {
+    /// shots that hit an enemy, whether or not they killed it
+    pub hits: i32,
+    /// kills since the player was last hit
+    pub streak: u32,
+    pub best_streak: u32,
+    /// kills in the current combo
+    pub combo: u32,
+    pub best_combo: u32,
+    /// seconds left to keep the combo going
+    combo_secs: f32,
+    pub combo_bonus: i32,
+    pub curve_bonus: i32,
+    pub streak_bonus: i32,
+}
+
+impl Scoring {
+    pub fn tick(&mut self, dt: f32) {
+        self.combo_secs -= dt;
+        if self.combo_secs <= 0.0 {
+            self.combo = 0;
+        }
+    }
+
+    pub fn hit(&mut self) {
+        self.hits += 1;
+    }
+
+    /// An enemy worth `points` was killed by a shot the field turned through
+    /// `turned` radians.
+    pub fn kill(&mut self, rules: &ScoringRules, points: i32, turned: f32) {
+        self.combo += 1;
+        self.best_combo = self.best_combo.max(self.combo);
+        self.combo_secs = rules.combo_window;
+        self.combo_bonus += (points as f32 * (self.multiplier(rules) - 1.0)).round() as i32;
+
+        self.streak += 1;
+        self.best_streak = self.best_streak.max(self.streak);
+        if rules.streak_length > 0 && self.streak.is_multiple_of(rules.streak_length) {
+            self.streak_bonus += rules.streak_bonus;
+        }
+
+        let degrees = turned.to_degrees().min(rules.curve_max);
+        if degrees >= rules.curve_min {
+            self.curve_bonus += (rules.curve_bonus * degrees / 90.0).round() as i32;
+        }
+    }
+
+    /// The player got hit, which ends their streak.
+    pub fn hurt(&mut self) {
+        self.streak = 0;
+    }
+
+    /// What the current combo multiplies an enemy's points by.
+    pub fn multiplier(&self, rules: &ScoringRules) -> f32 {
+        let steps = self.combo.saturating_sub(1) as f32;
+        (1.0 + rules.combo_step * steps).min(rules.max_multiplier.max(1.0))
+    }
+
+    pub fn bonus(&self) -> i32 {
+        self.combo_bonus + self.curve_bonus + self.streak_bonus
+    }
+}
+
+/// How a run's score was made up, for the end screen and to send along with
+/// the score.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Breakdown {
+    pub kills: i32,
+    pub enemy_points: i32,
+    pub combo_bonus: i32,
+    pub curve_bonus: i32,
+    pub streak_bonus: i32,
+    pub projectiles: i32,
+    pub hits: i32,
+    pub collisions: i32,
+    pub best_streak: u32,
+    pub best_combo: u32,
+    pub score: i32,
+}
+
+impl Breakdown {
+    pub fn new(world: &World) -> Breakdown {
+        let scoring = &world.scoring;
+        Breakdown {
+            kills: world.num_enemies_shot,
+            enemy_points: world.enemy_points,
+            combo_bonus: scoring.combo_bonus,
+            curve_bonus: scoring.curve_bonus,
+            streak_bonus: scoring.streak_bonus,
+            projectiles: world.num_projectiles,
+            hits: scoring.hits,
+            collisions: world.num_collisions,
+            best_streak: scoring.best_streak,
+            best_combo: scoring.best_combo,
+            score: world.score(),
+        }
+    }
+
+    /// The fraction of shots that hit something, if any were fired.
+    pub fn accuracy(&self) -> Option<f32> {
+        (self.projectiles > 0).then(|| self.hits as f32 / self.projectiles as f32)
+    }
+
+    /// The stats, and the bonuses if they count, a line at a time, for the
+    /// end screen.
+    pub fn lines(&self, bonuses: bool) -> Vec<String> {
+        let accuracy = match self.accuracy() {
+            Some(accuracy) => format!("{:.0}%", 100.0 * accuracy),
+            None => "-".to_owned(),
+        };
+        let mut lines = vec![
+            format!("accuracy {} ({}/{})", accuracy, self.hits, self.projectiles),
+            format!("best streak {}", self.best_streak),
+            format!("best combo {}", self.best_combo),
+        ];
+        if bonuses {
+            lines.extend([
+                format!("combo bonus +{}", self.combo_bonus),
+                format!("curve bonus +{}", self.curve_bonus),
+                format!("streak bonus +{}", self.streak_bonus),
+            ]);
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Kills `count` enemies worth 100 each with straight shots, one after
+    /// the other.
+    fn kill(scoring: &mut Scoring, rules: &ScoringRules, count: u32) {
+        for _ in 0..count {
+            scoring.kill(rules, 100, 0.0);
+        }
+    }
+
+    #[test]
+    fn combos_multiply_up_to_a_cap() {
+        let rules = ScoringRules::default();
+        let mut scoring = Scoring::default();
+        let mut multipliers = vec![];
+        for _ in 0..6 {
+            kill(&mut scoring, &rules, 1);
+            multipliers.push(scoring.multiplier(&rules));
+        }
+        assert_eq!(multipliers, [1.0, 1.5, 2.0, 2.5, 3.0, 3.0]);
+        assert_eq!(scoring.combo_bonus, 50 + 100 + 150 + 200 + 200);
+        assert_eq!(scoring.best_combo, 6);
+
+        // and end once the window's gone by without a kill
+        scoring.tick(0.5);
+        assert_eq!(scoring.combo, 6);
+        scoring.tick(0.5);
+        assert_eq!(scoring.combo, 0);
+        kill(&mut scoring, &rules, 1);
+        assert_eq!(scoring.multiplier(&rules), 1.0);
+        assert_eq!(scoring.combo_bonus, 700);
+        assert_eq!(scoring.best_combo, 6);
+    }
+
+    #[test]
+    fn streaks_pay_out_until_the_player_is_hit() {
+        let rules = ScoringRules::default();
+        let mut scoring = Scoring::default();
+        kill(&mut scoring, &rules, 9);
+        assert_eq!(scoring.streak_bonus, 0);
+        kill(&mut scoring, &rules, 1);
+        assert_eq!(scoring.streak_bonus, 500);
+        kill(&mut scoring, &rules, 5);
+        scoring.hurt();
+        assert_eq!(scoring.streak, 0);
+        kill(&mut scoring, &rules, 9);
+        assert_eq!(scoring.streak_bonus, 500);
+        kill(&mut scoring, &rules, 1);
+        assert_eq!(scoring.streak_bonus, 1000);
+        assert_eq!(scoring.best_streak, 15);
+    }
+
+    #[test]
+    fn curve_bonus_needs_enough_turn_and_is_capped() {
+        let rules = ScoringRules::default();
+        let curve = |degrees: f32| {
+            let mut scoring = Scoring::default();
+            scoring.kill(&rules, 100, degrees.to_radians());
+            scoring.curve_bonus
+        };
+        assert_eq!(curve(0.0), 0);
+        assert_eq!(curve(44.0), 0);
+        assert_eq!(curve(45.0), 25);
+        assert_eq!(curve(90.0), 50);
+        assert_eq!(curve(180.0), 100);
+        assert_eq!(curve(720.0), 100);
+    }
+
+    #[test]
+    fn zero_turns_a_bonus_off() {
+        let rules = ScoringRules {
+            combo_step: 0.0,
+            streak_bonus: 0,
+            curve_bonus: 0.0,
+            ..ScoringRules::default()
+        };
+        let mut scoring = Scoring::default();
+        for _ in 0..20 {
+            scoring.kill(&rules, 100, std::f32::consts::PI);
+        }
+        assert_eq!(scoring.bonus(), 0);
+
+        let rules = ScoringRules {
+            streak_length: 0,
+            ..ScoringRules::default()
+        };
+        let mut scoring = Scoring::default();
+        kill(&mut scoring, &rules, 20);
+        assert_eq!(scoring.streak_bonus, 0);
+        // the others still count
+        assert!(scoring.bonus() > 0);
+        assert_eq!(
+            scoring.bonus(),
+            scoring.combo_bonus + scoring.curve_bonus + scoring.streak_bonus
+        );
+    }
+
+    #[test]
+    fn accuracy() {
+        let breakdown = |hits: i32, projectiles: i32| Breakdown {
+            kills: 0,
+            enemy_points: 0,
+            combo_bonus: 0,
+            curve_bonus: 0,
+            streak_bonus: 0,
+            projectiles,
+            hits,
+            collisions: 0,
+            best_streak: 0,
+            best_combo: 0,
+            score: 0,
+        };
+        assert_eq!(breakdown(3, 4).accuracy(), Some(0.75));
+        assert_eq!(breakdown(0, 4).accuracy(), Some(0.0));
+        assert_eq!(breakdown(0, 0).accuracy(), None);
+        assert_eq!(breakdown(3, 4).lines(false)[0], "accuracy 75% (3/4)");
+        assert_eq!(breakdown(0, 0).lines(false)[0], "accuracy - (0/0)");
+        assert_eq!(breakdown(0, 0).lines(false).len(), 3);
+        assert_eq!(breakdown(0, 0).lines(true).len(), 6);
+    }
+}
@@ -0,0 +1,1326 @@
+use macroquad::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::enemy::{pick, Behaviour, Enemy};
+use crate::field::{Field, VectorField};
+use crate::grid::FieldGrid;
+use crate::map::{Map, Survival};
+use crate::mode::GameMode;
+use crate::obstacle::{Doublet, Obstacle};
+use crate::physics::Physics;
+use crate::rng::Rng;
+use crate::scoring::Scoring;
+use crate::spatial::SpatialHash;
+use crate::wave::{Telegraph, WaveState, TELEGRAPH_SECS};
+use crate::weapon::{Projectile, ProjectileKind, HOMING_ACCEL, HOMING_RANGE};
+
+pub const PLAYER_MOVEMENT: f32 = 1000.0;
+pub const PLAYER_MAX_MOVEMENT_SPEED: f32 = 1000.0;
+pub const PLAYER_RADIUS: f32 = 15.0;
+pub const PROJECTILE_INIT_SPEED: f32 = 1.5 * PLAYER_MAX_MOVEMENT_SPEED;
+pub const PROJECTILE_RADIUS: f32 = 5.0;
+/// How strongly the field pushes projectiles and enemies. These are the
+/// strengths the game started out with, but back then the push built up
+/// from one frame to the next instead of being felt afresh each tick, so
+/// bodies don't move quite the way they used to with them.
+pub const PROJECTILE_INERTIA: f32 = 1.0;
+pub const ENEMY_INIT_SPEED: f32 = 0.5 * PLAYER_MAX_MOVEMENT_SPEED;
+pub const ENEMY_INERTIA: f32 = 0.01;
+pub const ENEMY_RADIUS: f32 = 50.0;
+pub const FRICTION: f32 = 800.0;
+pub const MAX_ENEMIES: usize = 5;
+pub const BOUNCE_BOOST: f32 = 1.0;
+pub const GAME_TIME_SECS: f32 = 30.0;
+/// Everything in the simulation is measured in logical pixels, whatever the
+/// window size. Arenas are this big unless the map says otherwise, and this
+/// is how much of the arena the front end shows at once, scaled to fit.
+pub const ARENA: Vec2 = Vec2::new(1600.0, 900.0);
+
+/// Physics always advances in steps of this size, whatever the frame rate,
+/// so that the same inputs give the same run on every machine.
+pub const TICK_RATE: f32 = 120.0;
+pub const TICK_DT: f32 = 1.0 / TICK_RATE;
+/// How far past the edge of the arena a baked field reaches, so enemies
+/// coming in from outside are pushed around properly.
+pub const BAKE_MARGIN: f32 = 100.0;
+/// Longest frame the accumulator will try to catch up on, so a stall doesn't
+/// turn into hundreds of ticks at once.
+const MAX_FRAME_TIME: f32 = 0.25;
+/// Smallest cell the enemy broad phase uses, so tiny enemies in a big arena
+/// don't mean millions of cells.
+const MIN_HASH_CELL: f32 = 32.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Integrator {
+    ExplicitEuler,
+    SemiImplicitEuler,
+    Rk4,
+}
+
+/// Which integrator each kind of body uses.
+#[derive(Clone, Copy, Debug)]
+pub struct Integrators {
+    pub player: Integrator,
+    pub projectile: Integrator,
+    pub enemy: Integrator,
+}
+
+impl Default for Integrators {
+    fn default() -> Integrators {
+        Integrators {
+            player: Integrator::SemiImplicitEuler,
+            projectile: Integrator::Rk4,
+            enemy: Integrator::SemiImplicitEuler,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Body {
+    pub pos: Vec2,
+    pub vel: Vec2,
+    /// position before the last step, for interpolating between ticks
+    pub prev_pos: Vec2,
+    pub physics: Physics,
+}
+
+impl Body {
+    pub fn new(pos: Vec2, vel: Vec2) -> Body {
+        Body {
+            pos,
+            vel,
+            prev_pos: pos,
+            physics: Physics::default(),
+        }
+    }
+
+    pub fn with_physics(self, physics: Physics) -> Body {
+        Body { physics, ..self }
+    }
+
+    /// Where to draw the body when `alpha` of the next tick has elapsed.
+    pub fn lerp_pos(&self, alpha: f32) -> Vec2 {
+        self.prev_pos.lerp(self.pos, alpha)
+    }
+
+    /// Advances the body by `dt` as its physics says it should move, pushing
+    /// itself with `thrust` through a field that's `field` at each position.
+    pub fn step(
+        &mut self,
+        dt: f32,
+        integrator: Integrator,
+        thrust: Vec2,
+        field: impl Fn(Vec2) -> Vec2,
+    ) {
+        let physics = self.physics;
+        self.integrate(dt, integrator, |pos, vel| {
+            physics.accel(thrust, field(pos), vel)
+        });
+        self.vel = physics.limit(self.vel);
+    }
+
+    /// Advances the body by `dt`, where `accel` gives the acceleration for a
+    /// position and velocity.
+    pub fn integrate(
+        &mut self,
+        dt: f32,
+        integrator: Integrator,
+        accel: impl Fn(Vec2, Vec2) -> Vec2,
+    ) {
+        self.prev_pos = self.pos;
+        match integrator {
+            Integrator::ExplicitEuler => {
+                let acc = accel(self.pos, self.vel);
+                self.pos += dt * self.vel;
+                self.vel += dt * acc;
+            }
+            Integrator::SemiImplicitEuler => {
+                self.vel += dt * accel(self.pos, self.vel);
+                self.pos += dt * self.vel;
+            }
+            Integrator::Rk4 => {
+                let (p1, v1) = (self.pos, self.vel);
+                let a1 = accel(p1, v1);
+                let (p2, v2) = (p1 + 0.5 * dt * v1, v1 + 0.5 * dt * a1);
+                let a2 = accel(p2, v2);
+                let (p3, v3) = (p1 + 0.5 * dt * v2, v1 + 0.5 * dt * a2);
+                let a3 = accel(p3, v3);
+                let (p4, v4) = (p1 + dt * v3, v1 + dt * a3);
+                let a4 = accel(p4, v4);
+                self.pos += dt / 6.0 * (v1 + 2.0 * v2 + 2.0 * v3 + v4);
+                self.vel += dt / 6.0 * (a1 + 2.0 * a2 + 2.0 * a3 + a4);
+            }
+        }
+    }
+
+    pub fn bounds_clamp(&mut self, arena: Vec2) {
+        if self.pos.x < 0.0 {
+            self.pos.x = 0.0;
+            self.vel.x = BOUNCE_BOOST * PLAYER_MAX_MOVEMENT_SPEED;
+        }
+        if self.pos.x > arena.x {
+            self.pos.x = arena.x;
+            self.vel.x = -BOUNCE_BOOST * PLAYER_MAX_MOVEMENT_SPEED;
+        }
+        if self.pos.y < 0.0 {
+            self.pos.y = 0.0;
+            self.vel.y = BOUNCE_BOOST * PLAYER_MAX_MOVEMENT_SPEED;
+        }
+        if self.pos.y > arena.y {
+            self.pos.y = arena.y;
+            self.vel.y = -BOUNCE_BOOST * PLAYER_MAX_MOVEMENT_SPEED;
+        }
+    }
+}
+
+/// What happens to a body at the edge of the arena. Apart from `push`, they
+/// only act on bodies that are past an edge and still heading away from it,
+/// so enemies can fly in from outside.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Boundary {
+    /// put back on the edge and sent away from it at a fixed speed
+    Push,
+    /// bounces off, keeping `restitution` of its speed
+    Reflect {
+        #[serde(default = "default_restitution")]
+        restitution: f32,
+    },
+    /// comes back in on the other side
+    Wrap,
+    /// is removed, or for the player, counts as a collision
+    Kill,
+    /// stops dead on the edge
+    Sticky,
+    /// carries on; enemies are removed once they're far enough away
+    Open,
+}
+
+fn default_restitution() -> f32 {
+    1.0
+}
+
+impl Boundary {
+    /// Returns false if the body should be killed.
+    pub fn apply(self, body: &mut Body, arena: Vec2) -> bool {
+        if self == Boundary::Push {
+            body.bounds_clamp(arena);
+            return true;
+        }
+        for axis in 0..2 {
+            let (pos, vel) = (body.pos[axis], body.vel[axis]);
+            let edge = if pos < 0.0 && vel < 0.0 {
+                0.0
+            } else if pos > arena[axis] && vel > 0.0 {
+                arena[axis]
+            } else {
+                continue;
+            };
+            match self {
+                Boundary::Push | Boundary::Open => {}
+                Boundary::Reflect { restitution } => {
+                    body.pos[axis] = edge;
+                    body.vel[axis] = -restitution * vel;
+                }
+                Boundary::Wrap => {
+                    let shift = if edge == 0.0 {
+                        arena[axis]
+                    } else {
+                        -arena[axis]
+                    };
+                    body.pos[axis] += shift;
+                    body.prev_pos[axis] += shift;
+                }
+                Boundary::Kill => return false,
+                Boundary::Sticky => {
+                    body.pos[axis] = edge;
+                    body.vel = Vec2::ZERO;
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Everything the player can do during one step, already read from the
+/// keyboard and mouse (or from a bot or a replay).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Input {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+    /// where the player is aiming, in arena coordinates
+    pub aim: Vec2,
+    pub fire: bool,
+    /// switch to the map's weapon with this index
+    pub weapon: Option<usize>,
+}
+
+/// Turns variable frame times into a whole number of fixed ticks, carrying
+/// the remainder over to the next frame.
+#[derive(Default)]
+pub struct FixedStep {
+    accumulator: f32,
+}
+
+impl FixedStep {
+    /// Adds a frame's worth of time and returns how many ticks to run.
+    pub fn advance(&mut self, frame_time: f32) -> usize {
+        self.accumulator += frame_time.min(MAX_FRAME_TIME);
+        let ticks = (self.accumulator / TICK_DT) as usize;
+        self.accumulator -= ticks as f32 * TICK_DT;
+        ticks
+    }
+
+    /// How far we are between the last tick and the next one, from 0 to 1.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / TICK_DT
+    }
+}
+
+/// Things that happened during a step that the front end may want to react
+/// to, e.g. by playing a sound.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    Shot,
+    Hit,
+    /// an enemy was shot dead here
+    Killed(Vec2),
+    Collision,
+    TimeUp,
+    /// the run is over because the player shot every enemy a target run
+    /// asks for
+    Cleared,
+    /// the run is over because the player ran out of lives, or ran into an
+    /// enemy in an endless run without any
+    Died,
+}
+
+/// The whole game state. Advanced with `step`, never reads the screen,
+/// the clock or any other macroquad global.
+#[derive(Clone)]
+pub struct World {
+    pub arena: Vec2,
+    pub map: Map,
+    pub player: Body,
+    pub projectiles: Vec<Projectile>,
+    pub enemies: Vec<Enemy>,
+    /// enemies about to come in, on maps with waves
+    pub telegraphs: Vec<Telegraph>,
+    pub waves: WaveState,
+    /// true while a run is in progress; enemies only spawn and the timer only
+    /// runs while playing
+    pub playing: bool,
+    pub mode: GameMode,
+    /// infinite when the mode has no time limit
+    pub secs_left: f32,
+    /// how long the current run has gone on for, or the last one went on for
+    pub elapsed: f32,
+    /// seconds since the run started, which the field is sampled at. Keeps
+    /// counting between runs so the field still moves on the home screen.
+    pub time: f32,
+    /// index into the map's weapons of the one being fired
+    pub weapon: usize,
+    /// ticks until the trigger can fire again
+    pub cooldown: u32,
+    /// shots left for each of the map's weapons, `None` where unlimited
+    pub ammo: Vec<Option<u32>>,
+    pub num_projectiles: i32,
+    pub num_enemies_shot: i32,
+    /// what the enemies shot were worth
+    pub enemy_points: i32,
+    pub num_collisions: i32,
+    pub scoring: Scoring,
+    /// the player's health and lives for this run, if it has any
+    pub survival: Option<Survival>,
+    /// hits left before losing a life, when the player has health
+    pub health: u32,
+    /// lives left, when the player has health; the run ends when they're
+    /// all gone
+    pub lives: u32,
+    /// seconds before the player can be hit again, when the player has health
+    pub invulnerable: f32,
+    pub integrators: Integrators,
+    /// only check bodies in nearby cells of a grid for collisions, instead
+    /// of every pair. Gives the same results either way; turning it off is
+    /// only useful for comparing the two.
+    pub broad_phase: bool,
+    /// what the enemy spawns are drawn from; runs with the same seed, map and
+    /// inputs play out identically
+    pub seed: u64,
+    rng: Rng,
+    baked: Option<Baked>,
+    /// the flow around obstacles that deflect the field, for this tick
+    doublets: Vec<Doublet>,
+    /// where the enemies were the last time it was built
+    enemy_hash: SpatialHash,
+}
+
+/// The map's field sampled onto a grid, and what it was sampled for.
+#[derive(Clone)]
+struct Baked {
+    arena: Vec2,
+    field: Field,
+    grid: FieldGrid,
+}
+
+/// Looks up the field, straight from the map or from the baked grid, plus
+/// the flow around any obstacles that deflect it.
+#[derive(Clone, Copy)]
+struct FieldSampler<'a> {
+    field: &'a Field,
+    grid: Option<&'a FieldGrid>,
+    center: Vec2,
+    time: f32,
+    doublets: &'a [Doublet],
+}
+
+impl<'a> FieldSampler<'a> {
+    fn new(
+        map: &'a Map,
+        baked: &'a Option<Baked>,
+        doublets: &'a [Doublet],
+        arena: Vec2,
+        time: f32,
+    ) -> FieldSampler<'a> {
+        FieldSampler {
+            field: &map.field,
+            grid: baked
+                .as_ref()
+                .filter(|baked| baked.arena == arena)
+                .map(|baked| &baked.grid),
+            center: arena / 2.0,
+            time,
+            doublets,
+        }
+    }
+
+    /// The field as if there were no obstacles.
+    fn stream(&self, pos: Vec2) -> Vec2 {
+        match self.grid {
+            Some(grid) => grid.sample(pos - self.center),
+            None => self.field.force(pos - self.center, self.time),
+        }
+    }
+
+    fn force(&self, pos: Vec2) -> Vec2 {
+        self.doublets
+            .iter()
+            .fold(self.stream(pos), |force, doublet| {
+                force + doublet.deflection(pos)
+            })
+    }
+}
+
+/// Pushes `body` out of any obstacles it's overlapping, and returns the
+/// direction it was last pushed in.
+fn push_out(obstacles: &[Obstacle], body: &mut Body, radius: f32) -> Option<Vec2> {
+    let mut pushed = None;
+    for obstacle in obstacles {
+        if let Some((pos, normal)) = obstacle.collide(body.pos, radius) {
+            body.pos = pos;
+            pushed = Some(normal);
+        }
+    }
+    pushed
+}
+
+impl World {
+    pub fn new(map: Map, seed: u64) -> World {
+        let arena = Vec2::from_array(map.size);
+        let ammo = map.weapons.iter().map(|weapon| weapon.ammo).collect();
+        let player = Body::new(Vec2::new(arena.x - 30.0, arena.y - 30.0), Vec2::ZERO)
+            .with_physics(map.player);
+        World {
+            arena,
+            mode: GameMode::default(),
+            secs_left: map.time_limit,
+            elapsed: 0.0,
+            time: 0.0,
+            map,
+            player,
+            projectiles: vec![],
+            enemies: vec![],
+            telegraphs: vec![],
+            waves: WaveState::default(),
+            playing: false,
+            weapon: 0,
+            cooldown: 0,
+            ammo,
+            num_projectiles: 0,
+            num_enemies_shot: 0,
+            enemy_points: 0,
+            num_collisions: 0,
+            scoring: Scoring::default(),
+            survival: None,
+            health: 0,
+            lives: 0,
+            invulnerable: 0.0,
+            integrators: Integrators::default(),
+            broad_phase: true,
+            seed,
+            rng: Rng::new(seed),
+            baked: None,
+            doublets: vec![],
+            enemy_hash: SpatialHash::default(),
+        }
+    }
+
+    /// Starts a new run, keeping the player and any projectiles in flight.
+    pub fn start(&mut self) {
+        self.playing = true;
+        self.secs_left = self.mode.time_limit(&self.map).unwrap_or(f32::INFINITY);
+        self.time = 0.0;
+        self.elapsed = 0.0;
+        self.num_enemies_shot = 0;
+        self.enemy_points = 0;
+        self.num_projectiles = 0;
+        self.num_collisions = 0;
+        self.scoring = Scoring::default();
+        self.survival = self.mode.survival(&self.map);
+        let survival = self.survival.clone().unwrap_or_default();
+        self.health = survival.health;
+        self.lives = survival.lives;
+        self.invulnerable = 0.0;
+        self.enemies = vec![];
+        self.telegraphs = vec![];
+        self.waves = WaveState::default();
+        self.weapon = 0;
+        self.cooldown = 0;
+        self.ammo = self.map.weapons.iter().map(|weapon| weapon.ammo).collect();
+    }
+
+    pub fn score(&self) -> i32 {
+        self.mode.score(self)
+    }
+
+    /// Whether the run is over because the player died: out of lives when
+    /// they have health, or hit at all in an endless run without it.
+    pub fn is_dead(&self) -> bool {
+        match self.survival {
+            Some(_) => self.lives == 0,
+            None => self.is_endless() && self.num_collisions > 0,
+        }
+    }
+
+    pub fn field_force(&self, pos: Vec2) -> Vec2 {
+        FieldSampler::new(
+            &self.map,
+            &self.baked,
+            &self.doublets,
+            self.arena,
+            self.time,
+        )
+        .force(pos)
+    }
+
+    pub fn step(&mut self, dt: f32, input: &Input) -> Vec<Event> {
+        let mut events = vec![];
+        self.bake();
+        self.deflect();
+        self.step_player(dt, input, &mut events);
+        self.step_projectiles(dt, input, &mut events);
+        if self.playing {
+            self.step_enemies(dt, &mut events);
+            self.secs_left -= dt;
+            self.elapsed += dt;
+            self.scoring.tick(dt);
+            if let Some(event) = self.mode.finished(self) {
+                self.end();
+                events.push(event);
+            }
+        }
+        self.time += dt;
+        events
+    }
+
+    /// Whether the run goes on until the player dies rather than for a
+    /// time limit.
+    pub fn is_endless(&self) -> bool {
+        self.mode.time_limit(&self.map).is_none()
+    }
+
+    /// Puts the player back in the middle of the arena, standing still.
+    fn respawn(&mut self) {
+        self.player = Body::new(self.arena / 2.0, Vec2::ZERO).with_physics(self.map.player);
+    }
+
+    /// The player's been hit, by an enemy at `from` or by leaving the arena.
+    /// Without health that's just another collision; with it, the hit takes
+    /// health and then a life, and knocks the player away from `from`.
+    /// Returns whether the hit counted, which it doesn't while invulnerable.
+    fn hurt(&mut self, from: Option<Vec2>, events: &mut Vec<Event>) -> bool {
+        let Some(survival) = &self.survival else {
+            self.num_collisions += 1;
+            self.scoring.hurt();
+            events.push(Event::Collision);
+            return true;
+        };
+        if self.invulnerable > 0.0 {
+            return false;
+        }
+        self.num_collisions += 1;
+        self.scoring.hurt();
+        events.push(Event::Collision);
+        self.invulnerable = survival.invulnerable;
+        if let Some(from) = from {
+            self.player.vel = survival.knockback * (self.player.pos - from).normalize_or(Vec2::X);
+        }
+        self.health = self.health.saturating_sub(1);
+        if self.health == 0 {
+            self.lives = self.lives.saturating_sub(1);
+            if self.lives > 0 {
+                self.health = survival.health;
+                self.respawn();
+            }
+        }
+        true
+    }
+
+    fn end(&mut self) {
+        self.playing = false;
+        self.enemies = vec![];
+        self.telegraphs = vec![];
+    }
+
+    /// Samples the field onto a new grid, if the map wants one and the last
+    /// one was for a different arena or field, or for an animated field, too
+    /// long ago.
+    fn bake(&mut self) {
+        let Some(settings) = &self.map.bake else {
+            self.baked = None;
+            return;
+        };
+        let stale = match &self.baked {
+            Some(baked) => {
+                let since = self.time - baked.grid.time();
+                baked.arena != self.arena
+                    || baked.field != self.map.field
+                    || (self.map.field.is_animated() && !(0.0..settings.slice).contains(&since))
+            }
+            None => true,
+        };
+        if stale {
+            let half = self.arena / 2.0 + BAKE_MARGIN;
+            let grid = FieldGrid::bake(&self.map.field, -half, half, settings.cell, self.time);
+            self.baked = Some(Baked {
+                arena: self.arena,
+                field: self.map.field.clone(),
+                grid,
+            });
+        }
+    }
+
+    /// Works out how the field flows around the obstacles that deflect it,
+    /// from what it would be at each one without them.
+    fn deflect(&mut self) {
+        let sampler = FieldSampler::new(&self.map, &self.baked, &[], self.arena, self.time);
+        self.doublets = self
+            .map
+            .obstacles
+            .iter()
+            .filter(|obstacle| obstacle.deflect)
+            .flat_map(|obstacle| obstacle.deflector_circles())
+            .map(|(center, radius)| Doublet {
+                center,
+                radius,
+                stream: sampler.stream(center),
+            })
+            .collect();
+    }
+
+    fn step_player(&mut self, dt: f32, input: &Input, events: &mut Vec<Event>) {
+        let mut movement = Vec2::ZERO;
+        if input.right {
+            movement.x += PLAYER_MOVEMENT;
+        }
+        if input.left {
+            movement.x += -PLAYER_MOVEMENT;
+        }
+        if input.up {
+            movement.y += -PLAYER_MOVEMENT;
+        }
+        if input.down {
+            movement.y += PLAYER_MOVEMENT;
+        }
+
+        if !self
+            .map
+            .boundaries
+            .player
+            .apply(&mut self.player, self.arena)
+        {
+            // back to the middle, as if the player had run into an enemy
+            self.respawn();
+            if self.playing {
+                self.hurt(None, events);
+            }
+        }
+        self.invulnerable = (self.invulnerable - dt).max(0.0);
+
+        let field = FieldSampler::new(
+            &self.map,
+            &self.baked,
+            &self.doublets,
+            self.arena,
+            self.time,
+        );
+        self.player
+            .step(dt, self.integrators.player, movement, |pos| {
+                field.force(pos)
+            });
+        if let Some(normal) = push_out(&self.map.obstacles, &mut self.player, PLAYER_RADIUS) {
+            // slide along it
+            self.player.vel -= self.player.vel.dot(normal).min(0.0) * normal;
+        }
+    }
+
+    fn step_projectiles(&mut self, dt: f32, input: &Input, events: &mut Vec<Event>) {
+        self.fire(input, events);
+
+        self.hash_enemies();
+        // seeking shots pick a target at the start of the tick
+        let targets: Vec<Option<Vec2>> = self
+            .projectiles
+            .iter()
+            .map(|projectile| match projectile.kind {
+                ProjectileKind::Homing => self.nearest_enemy(projectile.body.pos, HOMING_RANGE),
+                _ => None,
+            })
+            .collect();
+        let field = FieldSampler::new(
+            &self.map,
+            &self.baked,
+            &self.doublets,
+            self.arena,
+            self.time,
+        );
+        let integrator = self.integrators.projectile;
+        for (projectile, target) in self.projectiles.iter_mut().zip(targets) {
+            let steer = target.map_or(Vec2::ZERO, |target| {
+                HOMING_ACCEL * (target - projectile.body.pos).normalize_or_zero()
+            });
+            let heading = projectile.body.vel;
+            projectile
+                .body
+                .step(dt, integrator, steer, |pos| field.force(pos));
+            projectile.life -= dt;
+            // seeking shots steer themselves, so only count what the field
+            // does to the rest
+            if projectile.kind != ProjectileKind::Homing {
+                let vel = projectile.body.vel;
+                projectile.turned += heading.perp_dot(vel).atan2(heading.dot(vel)).abs();
+            }
+        }
+
+        let (arena, boundary) = (self.arena, self.map.boundaries.projectiles);
+        let obstacles = &self.map.obstacles;
+        self.projectiles.retain_mut(|projectile| {
+            if projectile.life <= 0.0 {
+                return false;
+            }
+            if projectile.kind == ProjectileKind::Bouncing {
+                let bounce = Boundary::Reflect { restitution: 1.0 };
+                bounce.apply(&mut projectile.body, arena);
+                if let Some(normal) = push_out(obstacles, &mut projectile.body, PROJECTILE_RADIUS) {
+                    projectile.body.vel -= 2.0 * projectile.body.vel.dot(normal).min(0.0) * normal;
+                }
+                return true;
+            }
+            // obstacles are cover, so they stop everything else
+            boundary.apply(&mut projectile.body, arena)
+                && obstacles.iter().all(|obstacle| {
+                    obstacle
+                        .collide(projectile.body.pos, PROJECTILE_RADIUS)
+                        .is_none()
+                })
+        });
+
+        // each hit takes a point of hp; ones that leave the enemy alive or
+        // split it use up the projectile, ones that just kill it don't. The
+        // pieces of a split start out overlapping the shot, which would
+        // otherwise go on to kill them all.
+        let reach = self.enemy_reach();
+        let mut hp: Vec<u32> = self.enemies.iter().map(|enemy| enemy.hp).collect();
+        // how far the shot that killed each enemy had turned
+        let mut killed_by = vec![0.0; self.enemies.len()];
+        let mut spent = vec![false; self.projectiles.len()];
+        let mut hits = vec![false; self.projectiles.len()];
+        for (p, projectile) in self.projectiles.iter().enumerate() {
+            let pos = projectile.body.pos;
+            let hit = &mut hits[p];
+            for i in self.enemies_near(pos, reach) {
+                let enemy = &self.enemies[i];
+                if hp[i] > 0 && enemy.body.pos.distance(pos) <= enemy.radius {
+                    hp[i] -= 1;
+                    *hit = true;
+                    killed_by[i] = projectile.turned;
+                    if hp[i] > 0 || enemy.splits() {
+                        spent[p] = true;
+                        break;
+                    }
+                }
+            }
+        }
+        for (projectile, hit) in self.projectiles.iter_mut().zip(hits) {
+            if hit {
+                if !projectile.hit {
+                    self.scoring.hit();
+                }
+                projectile.hit = true;
+                events.push(Event::Hit);
+            }
+        }
+        let mut spent = spent.into_iter();
+        self.projectiles.retain(|_| !spent.next().unwrap_or(false));
+        let mut pieces = vec![];
+        for ((enemy, hp), turned) in self.enemies.iter_mut().zip(hp).zip(killed_by) {
+            if hp == 0 {
+                self.num_enemies_shot += 1;
+                self.enemy_points += enemy.score;
+                self.scoring.kill(&self.map.scoring, enemy.score, turned);
+                events.push(Event::Killed(enemy.body.pos));
+                pieces.extend(enemy.split());
+            }
+            enemy.hp = hp;
+        }
+        self.enemies.retain(|enemy| enemy.hp > 0);
+        self.enemies.extend(pieces);
+    }
+
+    /// Switches weapon if asked to, and fires the current one if the trigger
+    /// is held and it's ready and loaded.
+    fn fire(&mut self, input: &Input, events: &mut Vec<Event>) {
+        if !self.mode.can_fire() {
+            return;
+        }
+        self.cooldown = self.cooldown.saturating_sub(1);
+        if let Some(weapon) = input
+            .weapon
+            .filter(|&weapon| weapon < self.map.weapons.len())
+        {
+            self.weapon = weapon;
+        }
+        let Some(weapon) = self.map.weapons.get(self.weapon) else {
+            return;
+        };
+        let empty = self.ammo.get(self.weapon) == Some(&Some(0));
+        if !input.fire || self.cooldown > 0 || empty {
+            return;
+        }
+        if let Some(Some(ammo)) = self.ammo.get_mut(self.weapon) {
+            *ammo -= 1;
+        }
+        self.cooldown = (weapon.cooldown * TICK_RATE).round() as u32;
+        let before = self.projectiles.len();
+        self.projectiles
+            .extend(weapon.fire(self.player.pos, input.aim));
+        if self.playing {
+            self.num_projectiles += (self.projectiles.len() - before) as i32;
+        }
+        events.push(Event::Shot);
+    }
+
+    fn step_enemies(&mut self, dt: f32, events: &mut Vec<Event>) {
+        let arena = self.arena;
+        let settings = &self.map.enemies;
+        if self.map.waves.is_some() {
+            self.spawn_waves(dt);
+        } else if self.enemies.len() < settings.max {
+            let rng = &mut self.rng;
+            // maps without enemy types don't roll for one, so they spawn the
+            // same as they always have
+            let kind = if settings.types.is_empty() {
+                None
+            } else {
+                pick(&settings.types, rng.rand())
+            };
+            let radius = kind.map_or(settings.radius, |kind| kind.radius);
+            let pos_l = Vec2::new(-radius, (rng.rand() % arena.y as u32) as f32);
+            let pos_r = Vec2::new(arena.x + radius, (rng.rand() % arena.y as u32) as f32);
+            let pos_u = Vec2::new((rng.rand() % arena.x as u32) as f32, -radius);
+            let pos_d = Vec2::new((rng.rand() % arena.x as u32) as f32, arena.y + radius);
+            let pos = [pos_d, pos_l, pos_r, pos_u][(rng.rand() % 4) as usize];
+            self.enemies
+                .push(settings.launch(kind, pos, self.player.pos));
+        }
+
+        let field = FieldSampler::new(&self.map, &self.baked, &self.doublets, arena, self.time);
+        let integrator = self.integrators.enemy;
+        let player_pos = self.player.pos;
+        for enemy in &mut self.enemies {
+            let thrust = match enemy.behaviour {
+                Behaviour::Drifter => Vec2::ZERO,
+                _ => enemy.thrust(dt, player_pos, field.force(enemy.body.pos)),
+            };
+            enemy
+                .body
+                .step(dt, integrator, thrust, |pos| field.force(pos));
+        }
+        let boundary = self.map.boundaries.enemies;
+        self.enemies
+            .retain_mut(|enemy| boundary.apply(&mut enemy.body, arena));
+        for enemy in &mut self.enemies {
+            if let Some(normal) = push_out(&self.map.obstacles, &mut enemy.body, enemy.radius) {
+                // bounce off
+                enemy.body.vel -= 2.0 * enemy.body.vel.dot(normal).min(0.0) * normal;
+            }
+        }
+
+        self.hash_enemies();
+        let near = self.enemies_near(player_pos, self.enemy_reach());
+        let mut touching = vec![false; self.enemies.len()];
+        for i in near {
+            let enemy = &self.enemies[i];
+            if enemy.body.pos.distance(player_pos) <= enemy.radius {
+                // when the player has health they stay around to hit again
+                let from = enemy.body.pos;
+                touching[i] = self.hurt(Some(from), events) && self.survival.is_none();
+            }
+        }
+        let mut touching = touching.into_iter();
+        self.enemies.retain(|enemy| {
+            !touching.next().unwrap_or(false)
+                && enemy.body.pos.distance_squared(player_pos) <= arena.length_squared()
+        });
+    }
+
+    /// Marks where the enemies from waves that are due will come in, and
+    /// brings in the ones that have been marked long enough.
+    fn spawn_waves(&mut self, dt: f32) {
+        let Some(waves) = &self.map.waves else {
+            return;
+        };
+        let settings = &self.map.enemies;
+        for spawn in self.waves.due(waves, self.time) {
+            let radius = spawn
+                .enemy
+                .and_then(|i| settings.types.get(i))
+                .map_or(settings.radius, |kind| kind.radius);
+            let pos = spawn
+                .edge
+                .point(spawn.along, radius, self.arena, &mut self.rng);
+            self.telegraphs.push(Telegraph {
+                pos,
+                enemy: spawn.enemy,
+                secs_left: TELEGRAPH_SECS,
+            });
+        }
+        for telegraph in &mut self.telegraphs {
+            telegraph.secs_left -= dt;
+            if telegraph.secs_left > 0.0 {
+                continue;
+            }
+            let kind = telegraph.enemy.and_then(|i| settings.types.get(i));
+            self.enemies
+                .push(settings.launch(kind, telegraph.pos, self.player.pos));
+        }
+        self.telegraphs
+            .retain(|telegraph| telegraph.secs_left > 0.0);
+    }
+
+    /// The radius of the biggest enemy, so broad phase queries that far
+    /// around something find every enemy touching it.
+    fn enemy_reach(&self) -> f32 {
+        self.enemies
+            .iter()
+            .map(|enemy| enemy.radius)
+            .fold(self.map.enemies.radius, f32::max)
+    }
+
+    /// Rebuilds the broad phase from where the enemies are now, if it's on.
+    fn hash_enemies(&mut self) {
+        if !self.broad_phase {
+            return;
+        }
+        let radius = self.enemy_reach();
+        self.enemy_hash.build(
+            Vec2::splat(-radius),
+            self.arena + radius,
+            (2.0 * radius).max(MIN_HASH_CELL),
+            self.enemies.iter().map(|enemy| enemy.body.pos),
+        );
+    }
+
+    /// Where the closest enemy within `range` of `pos` is, if there is one.
+    fn nearest_enemy(&self, pos: Vec2, range: f32) -> Option<Vec2> {
+        self.enemies_near(pos, range)
+            .into_iter()
+            .map(|i| self.enemies[i].body.pos)
+            .filter(|enemy| enemy.distance(pos) <= range)
+            .min_by(|a, b| a.distance_squared(pos).total_cmp(&b.distance_squared(pos)))
+    }
+
+    /// The enemies that could be within `radius` of `pos`: those in nearby
+    /// cells with the broad phase on, and all of them with it off. Either
+    /// way they're in order, since a shot stops at the first enemy it can't
+    /// go through and the player is knocked back by the first one they hit.
+    fn enemies_near(&self, pos: Vec2, radius: f32) -> Vec<usize> {
+        if !self.broad_phase {
+            return (0..self.enemies.len()).collect();
+        }
+        let mut near: Vec<usize> = self.enemy_hash.query(pos, radius).collect();
+        near.sort_unstable();
+        near
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enemy::ENEMY_SCORE;
+    use crate::field::{Circular, Square};
+    use crate::map::builtin_maps;
+    use crate::wave::{Edge, Spawn, Wave, Waves};
+    use crate::weapon::{default_weapons, Weapon};
+
+    /// Plays `ticks` ticks of a run with the same made up inputs every time,
+    /// and returns everything that happened.
+    fn play(world: &mut World, ticks: u32) -> Vec<Event> {
+        world.start();
+        let mut events = vec![];
+        for tick in 0..ticks {
+            let input = Input {
+                up: tick % 300 < 100,
+                left: tick % 200 < 120,
+                aim: Vec2::new((tick * 7 % 1600) as f32, 300.0),
+                fire: tick % 3 == 0,
+                ..Input::default()
+            };
+            events.extend(world.step(TICK_DT, &input));
+        }
+        events
+    }
+
+    fn positions(world: &World) -> Vec<Vec2> {
+        let enemies = world.enemies.iter().map(|enemy| enemy.body.pos);
+        let projectiles = world
+            .projectiles
+            .iter()
+            .map(|projectile| projectile.body.pos);
+        enemies.chain(projectiles).collect()
+    }
+
+    #[test]
+    fn same_seed_and_inputs_play_out_the_same() {
+        let map = Map::new("test", Field::Circular(Circular { strength: 200.0 }));
+        let mut a = World::new(map.clone(), 7);
+        let mut b = World::new(map.clone(), 7);
+        let events = play(&mut a, 1200);
+        assert_eq!(events, play(&mut b, 1200));
+        assert!(events.contains(&Event::Shot));
+        assert_eq!(a.player.pos, b.player.pos);
+        assert_eq!(a.player.vel, b.player.vel);
+        assert_eq!(positions(&a), positions(&b));
+        assert_eq!(a.score(), b.score());
+
+        // and the seed is what decides where enemies come in
+        let mut c = World::new(map, 8);
+        play(&mut c, 1200);
+        assert_ne!(positions(&a), positions(&c));
+    }
+
+    #[test]
+    fn boundaries() {
+        let arena = Vec2::new(100.0, 50.0);
+        let leaving = || Body::new(Vec2::new(110.0, 20.0), Vec2::new(30.0, -5.0));
+
+        let mut body = leaving();
+        assert!(Boundary::Push.apply(&mut body, arena));
+        assert_eq!(body.pos, Vec2::new(100.0, 20.0));
+        assert!(body.vel.x < 0.0);
+
+        let mut body = leaving();
+        let reflect = Boundary::Reflect { restitution: 0.5 };
+        assert!(reflect.apply(&mut body, arena));
+        assert_eq!(body.pos, Vec2::new(100.0, 20.0));
+        assert_eq!(body.vel, Vec2::new(-15.0, -5.0));
+
+        let mut body = leaving();
+        assert!(Boundary::Wrap.apply(&mut body, arena));
+        assert_eq!(body.pos, Vec2::new(10.0, 20.0));
+        assert_eq!(body.vel, Vec2::new(30.0, -5.0));
+
+        assert!(!Boundary::Kill.apply(&mut leaving(), arena));
+
+        let mut body = leaving();
+        assert!(Boundary::Sticky.apply(&mut body, arena));
+        assert_eq!(body.pos, Vec2::new(100.0, 20.0));
+        assert_eq!(body.vel, Vec2::ZERO);
+
+        let mut body = leaving();
+        assert!(Boundary::Open.apply(&mut body, arena));
+        assert_eq!(body.pos, Vec2::new(110.0, 20.0));
+
+        // bodies outside but on their way in are left alone
+        let arriving = Body::new(Vec2::new(110.0, 20.0), Vec2::new(-30.0, 0.0));
+        for boundary in [
+            Boundary::Reflect { restitution: 1.0 },
+            Boundary::Wrap,
+            Boundary::Kill,
+            Boundary::Sticky,
+        ] {
+            let mut body = arriving.clone();
+            assert!(boundary.apply(&mut body, arena));
+            assert_eq!(body.pos, arriving.pos);
+            assert_eq!(body.vel, arriving.vel);
+        }
+    }
+
+    #[test]
+    fn broad_phase_changes_nothing() {
+        let mut kills = 0;
+        for map in builtin_maps() {
+            let mut naive = World::new(map.clone(), 11);
+            naive.broad_phase = false;
+            let mut hashed = World::new(map.clone(), 11);
+            let events = play(&mut naive, 2400);
+            assert_eq!(events, play(&mut hashed, 2400), "{}", map.name);
+            kills += events
+                .iter()
+                .filter(|event| matches!(event, Event::Killed(_)))
+                .count();
+            assert_eq!(naive.player.pos, hashed.player.pos, "{}", map.name);
+            assert_eq!(positions(&naive), positions(&hashed), "{}", map.name);
+            assert_eq!(naive.score(), hashed.score(), "{}", map.name);
+        }
+        assert!(kills > 0);
+    }
+
+    /// A world with a run under way on a map with no field and no enemies,
+    /// and `weapons` to fire.
+    fn armed_world(weapons: Vec<Weapon>) -> World {
+        let mut map = Map::new(
+            "armed",
+            Field::Square(Square {
+                scale: 0.0,
+                c: [0.0, 0.0],
+            }),
+        );
+        map.enemies.max = 0;
+        map.weapons = weapons;
+        let mut world = World::new(map, 1);
+        world.start();
+        world
+    }
+
+    /// Holds the trigger for `ticks` ticks and counts the shots.
+    fn hold_fire(world: &mut World, ticks: u32) -> usize {
+        let input = Input {
+            aim: world.arena / 2.0,
+            fire: true,
+            ..Input::default()
+        };
+        (0..ticks)
+            .flat_map(|_| world.step(TICK_DT, &input))
+            .filter(|event| *event == Event::Shot)
+            .count()
+    }
+
+    #[test]
+    fn weapons_wait_for_their_cooldown() {
+        // a shot every 6 ticks
+        let mut world = armed_world(default_weapons());
+        assert_eq!(hold_fire(&mut world, 60), 10);
+        assert_eq!(world.num_projectiles, 10);
+    }
+
+    #[test]
+    fn weapons_run_out_of_ammo() {
+        let weapons = default_weapons();
+        let cannon = Weapon {
+            ammo: Some(3),
+            ..weapons[1].clone()
+        };
+        let mut world = armed_world(vec![cannon, weapons[0].clone()]);
+        assert_eq!(hold_fire(&mut world, 1200), 3);
+        assert_eq!(world.ammo, [Some(0), None]);
+
+        // but others still have theirs
+        let switch = Input {
+            weapon: Some(1),
+            ..Input::default()
+        };
+        world.step(TICK_DT, &switch);
+        assert_eq!(world.weapon, 1);
+        assert!(hold_fire(&mut world, 60) > 0);
+    }
+
+    #[test]
+    fn switching_weapons() {
+        let mut world = armed_world(default_weapons());
+        let switch = |weapon| Input {
+            weapon: Some(weapon),
+            ..Input::default()
+        };
+        world.step(TICK_DT, &switch(2));
+        assert_eq!(world.weapon, 2);
+        // there's no sixth weapon to switch to
+        world.step(TICK_DT, &switch(5));
+        assert_eq!(world.weapon, 2);
+        // every pellet counts as a projectile
+        assert_eq!(hold_fire(&mut world, 1), 1);
+        assert_eq!(world.num_projectiles, 5);
+        assert_eq!(world.ammo[2], Some(29));
+    }
+
+    fn enemy_at(pos: Vec2) -> Enemy {
+        Enemy::new(Body::new(pos, Vec2::ZERO), 20.0)
+    }
+
+    fn shoot(world: &mut World, pos: Vec2) -> Vec<Event> {
+        world
+            .projectiles
+            .push(Projectile::new(pos, Vec2::ZERO, ProjectileKind::Standard));
+        world.step(TICK_DT, &Input::default())
+    }
+
+    #[test]
+    fn shots_kill_enemies() {
+        let mut world = armed_world(default_weapons());
+        let pos = Vec2::new(400.0, 400.0);
+        world.enemies.push(enemy_at(pos));
+        let events = shoot(&mut world, pos);
+        assert!(events.contains(&Event::Hit));
+        assert!(events.contains(&Event::Killed(pos)));
+        assert!(world.enemies.is_empty());
+        assert_eq!(world.num_enemies_shot, 1);
+        assert_eq!(world.enemy_points, ENEMY_SCORE);
+        assert_eq!(world.scoring.hits, 1);
+        // a shot that kills goes on through
+        assert_eq!(world.projectiles.len(), 1);
+    }
+
+    #[test]
+    fn shots_that_leave_enemies_alive_are_used_up() {
+        let mut world = armed_world(default_weapons());
+        let pos = Vec2::new(400.0, 400.0);
+        world.enemies.push(Enemy {
+            hp: 2,
+            ..enemy_at(pos)
+        });
+        shoot(&mut world, pos);
+        assert_eq!(world.enemies.len(), 1);
+        assert_eq!(world.enemies[0].hp, 1);
+        assert!(world.projectiles.is_empty());
+        assert_eq!(world.num_enemies_shot, 0);
+        assert_eq!(world.scoring.hits, 1);
+    }
+
+    #[test]
+    fn shots_that_split_enemies_are_used_up() {
+        let mut world = armed_world(default_weapons());
+        let pos = Vec2::new(400.0, 400.0);
+        world.enemies.push(Enemy {
+            behaviour: Behaviour::Splitter {
+                pieces: 3,
+                generations: 1,
+            },
+            generations: 1,
+            ..enemy_at(pos)
+        });
+        shoot(&mut world, pos);
+        assert_eq!(world.num_enemies_shot, 1);
+        assert!(world.projectiles.is_empty());
+        // and so don't go on to kill the pieces
+        assert_eq!(world.enemies.len(), 3);
+    }
+
+    #[test]
+    fn waves_are_marked_before_they_come_in() {
+        let mut world = armed_world(default_weapons());
+        world.map.waves = Some(Waves {
+            waves: vec![Wave {
+                at: 1.0,
+                spawns: vec![Spawn {
+                    enemy: None,
+                    count: 1,
+                    interval: 0.0,
+                    edge: Edge::Left,
+                    along: Some(0.5),
+                }],
+            }],
+            endless: false,
+            rest: 5.0,
+            ramp: 0.5,
+        });
+        world.start();
+        let mut marked = None;
+        let mut came_in = None;
+        for tick in 0..480u32 {
+            world.step(TICK_DT, &Input::default());
+            if marked.is_none() && !world.telegraphs.is_empty() {
+                marked = Some(tick);
+            }
+            if came_in.is_none() && !world.enemies.is_empty() {
+                came_in = Some(tick);
+                assert!(world.telegraphs.is_empty());
+            }
+        }
+        let (marked, came_in) = (marked.unwrap(), came_in.unwrap());
+        // give or take a tick, as time is added up a tick at a time
+        assert!(marked.abs_diff(TICK_RATE as u32) <= 1, "{}", marked);
+        let lead = came_in - marked;
+        assert!(
+            lead.abs_diff((TELEGRAPH_SECS * TICK_RATE) as u32) <= 1,
+            "{}",
+            lead
+        );
+    }
+
+    #[test]
+    fn running_into_enemies() {
+        let mut world = armed_world(default_weapons());
+        world.enemies.push(enemy_at(world.player.pos));
+        let events = world.step(TICK_DT, &Input::default());
+        assert!(events.contains(&Event::Collision));
+        assert_eq!(world.num_collisions, 1);
+        // without health the enemy goes, so it only counts once
+        assert!(world.enemies.is_empty());
+    }
+
+    #[test]
+    fn running_into_enemies_with_health() {
+        let survival = Survival {
+            health: 2,
+            lives: 2,
+            ..Survival::default()
+        };
+        let mut world = armed_world(default_weapons());
+        world.map.survival = Some(survival.clone());
+        world.start();
+        let hit = |world: &mut World| {
+            let pos = world.player.pos;
+            world.enemies = vec![enemy_at(pos)];
+            world.step(TICK_DT, &Input::default())
+        };
+
+        hit(&mut world);
+        assert_eq!(world.num_collisions, 1);
+        assert_eq!(world.health, 1);
+        assert!(world.invulnerable > 0.0);
+        // the enemy stays, and the player's knocked away from it
+        assert_eq!(world.enemies.len(), 1);
+        assert!(world.player.vel.length() > 0.0);
+
+        // and can't be hit again until that wears off
+        hit(&mut world);
+        assert_eq!(world.num_collisions, 1);
+
+        // losing the last of their health costs a life
+        world.invulnerable = 0.0;
+        hit(&mut world);
+        assert_eq!((world.health, world.lives), (survival.health, 1));
+        assert_eq!(world.player.pos, world.arena / 2.0);
+
+        world.invulnerable = 0.0;
+        hit(&mut world);
+        world.invulnerable = 0.0;
+        let events = hit(&mut world);
+        assert_eq!(world.lives, 0);
+        assert!(world.is_dead());
+        assert!(events.contains(&Event::Died));
+        assert!(!world.playing);
+    }
+}
@@ -0,0 +1,157 @@
+use macroquad::math::Vec2;
+
+/// A broad phase for collisions: bodies bucketed into a uniform grid of
+/// square cells, so finding what might touch something only has to look at
+/// the few cells around it instead of every body. Bodies outside the grid go
+/// in the nearest cell on its edge, so nothing is ever missed, just checked
+/// more often.
+///
+/// Rebuilt from scratch every tick; the buffers are kept between builds.
+#[derive(Clone, Debug, Default)]
+pub struct SpatialHash {
+    min: Vec2,
+    cell: f32,
+    cols: usize,
+    rows: usize,
+    /// where each cell's bodies start in `bodies`, plus one past the end
+    starts: Vec<usize>,
+    /// body indices, grouped by cell
+    bodies: Vec<usize>,
+    /// the cell each body went in, while building
+    cells: Vec<usize>,
+}
+
+impl SpatialHash {
+    /// Buckets `positions` into cells `cell` pixels across covering the
+    /// rectangle from `min` to `max`. Queries give back indices into
+    /// `positions`.
+    pub fn build(
+        &mut self,
+        min: Vec2,
+        max: Vec2,
+        cell: f32,
+        positions: impl Iterator<Item = Vec2>,
+    ) {
+        let size = ((max - min) / cell).ceil().max(Vec2::ONE);
+        self.min = min;
+        self.cell = cell;
+        self.cols = size.x as usize;
+        self.rows = size.y as usize;
+
+        self.cells.clear();
+        for pos in positions {
+            let (col, row) = self.cell_of(pos);
+            self.cells.push(row * self.cols + col);
+        }
+
+        // counting sort: count each cell, turn the counts into where each
+        // cell starts, then drop the bodies into place
+        self.starts.clear();
+        self.starts.resize(self.cols * self.rows + 1, 0);
+        for &cell in &self.cells {
+            self.starts[cell + 1] += 1;
+        }
+        for i in 1..self.starts.len() {
+            self.starts[i] += self.starts[i - 1];
+        }
+        self.bodies.clear();
+        self.bodies.resize(self.cells.len(), 0);
+        let mut next = self.starts.clone();
+        for (body, &cell) in self.cells.iter().enumerate() {
+            self.bodies[next[cell]] = body;
+            next[cell] += 1;
+        }
+    }
+
+    /// Every body that could be within `radius` of `pos`, plus some that
+    /// aren't. Each one comes up once, in the order they were built from
+    /// within each cell.
+    pub fn query(&self, pos: Vec2, radius: f32) -> impl Iterator<Item = usize> + '_ {
+        let (min_col, min_row) = self.cell_of(pos - radius);
+        let (max_col, max_row) = self.cell_of(pos + radius);
+        (min_row..=max_row).flat_map(move |row| {
+            let first = row * self.cols;
+            let (start, end) = (
+                self.starts[first + min_col],
+                self.starts[first + max_col + 1],
+            );
+            self.bodies[start..end].iter().copied()
+        })
+    }
+
+    fn cell_of(&self, pos: Vec2) -> (usize, usize) {
+        let at = ((pos - self.min) / self.cell).floor();
+        let col = at.x.clamp(0.0, (self.cols - 1) as f32) as usize;
+        let row = at.y.clamp(0.0, (self.rows - 1) as f32) as usize;
+        (col, row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    /// Somewhere from `min` to `max`, on either side.
+    fn random(rng: &mut Rng, min: f32, max: f32) -> Vec2 {
+        let mut coord = || min + (max - min) * (rng.rand() as f32 / u32::MAX as f32);
+        Vec2::new(coord(), coord())
+    }
+
+    #[test]
+    fn finds_what_a_brute_force_scan_does() {
+        let mut rng = Rng::new(1);
+        // some of them off the grid, which still have to be found
+        let positions: Vec<Vec2> = (0..300).map(|_| random(&mut rng, -100.0, 1100.0)).collect();
+        let mut hash = SpatialHash::default();
+        hash.build(
+            Vec2::ZERO,
+            Vec2::splat(1000.0),
+            64.0,
+            positions.iter().copied(),
+        );
+        for _ in 0..200 {
+            let pos = random(&mut rng, -200.0, 1200.0);
+            let radius = 100.0 * (rng.rand() % 100) as f32 / 100.0;
+            let brute: Vec<usize> = (0..positions.len())
+                .filter(|&i| positions[i].distance(pos) <= radius)
+                .collect();
+
+            let mut found: Vec<usize> = hash.query(pos, radius).collect();
+            found.sort_unstable();
+            let candidates = found.len();
+            found.dedup();
+            assert_eq!(found.len(), candidates, "bodies came up twice");
+            found.retain(|&i| positions[i].distance(pos) <= radius);
+            assert_eq!(found, brute);
+        }
+    }
+
+    #[test]
+    fn keeps_build_order_within_a_cell() {
+        let positions = [
+            Vec2::new(10.0, 10.0),
+            Vec2::new(500.0, 500.0),
+            Vec2::new(20.0, 20.0),
+            Vec2::new(5.0, 30.0),
+        ];
+        let mut hash = SpatialHash::default();
+        hash.build(
+            Vec2::ZERO,
+            Vec2::splat(1000.0),
+            64.0,
+            positions.iter().copied(),
+        );
+        let found: Vec<usize> = hash.query(Vec2::new(20.0, 20.0), 1.0).collect();
+        assert_eq!(found, [0, 2, 3]);
+        // and can be built again from scratch
+        hash.build(
+            Vec2::ZERO,
+            Vec2::splat(1000.0),
+            64.0,
+            positions[1..].iter().copied(),
+        );
+        let found: Vec<usize> = hash.query(Vec2::new(20.0, 20.0), 1.0).collect();
+        assert_eq!(found, [1, 2]);
+    }
+}
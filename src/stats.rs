@@ -0,0 +1,183 @@
+use macroquad::math::Vec2;
+
+use crate::sim::{Event, Input, World, TICK_DT, TICK_RATE};
+
+/// How big the squares are that the player's time is counted in, across
+/// the arena.
+pub const HEAT_CELL: f32 = 50.0;
+
+/// What happened during one second of a run.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Second {
+    pub kills: u32,
+    pub shots: u32,
+    pub collisions: u32,
+    /// pixels the player moved
+    pub distance: f32,
+    /// seconds the player spent moving against the field
+    pub fighting: f32,
+    /// seconds the player spent going with it, or letting it carry them
+    pub riding: f32,
+}
+
+impl Second {
+    fn add(&mut self, other: &Second) {
+        self.kills += other.kills;
+        self.shots += other.shots;
+        self.collisions += other.collisions;
+        self.distance += other.distance;
+        self.fighting += other.fighting;
+        self.riding += other.riding;
+    }
+}
+
+/// A run's statistics, collected a tick at a time while it's played.
+#[derive(Clone, Debug)]
+pub struct Stats {
+    pub seconds: Vec<Second>,
+    /// ticks the player spent in each `HEAT_CELL` square of the arena, a
+    /// row at a time
+    pub heat: Vec<u32>,
+    pub cols: usize,
+    pub rows: usize,
+    /// where enemies were shot
+    pub kills: Vec<Vec2>,
+    ticks: usize,
+    last_pos: Option<Vec2>,
+}
+
+impl Stats {
+    pub fn new(arena: Vec2) -> Stats {
+        let size = (arena / HEAT_CELL).ceil().max(Vec2::ONE);
+        let (cols, rows) = (size.x as usize, size.y as usize);
+        Stats {
+            seconds: vec![],
+            heat: vec![0; cols * rows],
+            cols,
+            rows,
+            kills: vec![],
+            ticks: 0,
+            last_pos: None,
+        }
+    }
+
+    /// Counts a tick of the run that `world` was just stepped through with
+    /// `input`, which made `events` happen.
+    pub fn record(&mut self, world: &World, input: &Input, events: &[Event]) {
+        if self.ticks.is_multiple_of(TICK_RATE as usize) {
+            self.seconds.push(Second::default());
+        }
+        self.ticks += 1;
+        let second = self.seconds.last_mut().expect("pushed above");
+        for event in events {
+            match event {
+                Event::Shot => second.shots += 1,
+                Event::Collision => second.collisions += 1,
+                Event::Killed(pos) => {
+                    second.kills += 1;
+                    self.kills.push(*pos);
+                }
+                _ => {}
+            }
+        }
+
+        let pos = world.player.pos;
+        if let Some(last_pos) = self.last_pos {
+            second.distance += last_pos.distance(pos);
+        }
+        self.last_pos = Some(pos);
+        let movement = Vec2::new(
+            (input.right as i32 - input.left as i32) as f32,
+            (input.down as i32 - input.up as i32) as f32,
+        );
+        if movement.dot(world.field_force(pos)) < 0.0 {
+            second.fighting += TICK_DT;
+        } else {
+            second.riding += TICK_DT;
+        }
+
+        let cell = (pos / HEAT_CELL).floor();
+        if cell.x >= 0.0 && cell.y >= 0.0 {
+            let (col, row) = (cell.x as usize, cell.y as usize);
+            if col < self.cols && row < self.rows {
+                self.heat[row * self.cols + col] += 1;
+            }
+        }
+    }
+
+    /// Every second added up.
+    pub fn totals(&self) -> Second {
+        let mut totals = Second::default();
+        for second in &self.seconds {
+            totals.add(second);
+        }
+        totals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::{Field, Square};
+    use crate::map::Map;
+
+    #[test]
+    fn counts_each_second_and_adds_them_up() {
+        // pushing right all over the arena, gently enough to walk against
+        let mut map = Map::new(
+            "stats",
+            Field::Square(Square {
+                scale: 1e-5,
+                c: [1e6, 0.0],
+            }),
+        );
+        map.enemies.max = 0;
+        let mut world = World::new(map, 1);
+        world.start();
+        let mut stats = Stats::new(world.arena);
+        let killed_at = [Vec2::new(10.0, 20.0), Vec2::new(30.0, 40.0)];
+        let mut distance = 0.0;
+        let ticks = TICK_RATE as u32;
+        for tick in 0..2 * ticks {
+            // against the field for the first second, then standing still
+            let force = world.field_force(world.player.pos);
+            let input = Input {
+                left: tick < ticks && force.x > 0.0,
+                right: tick < ticks && force.x < 0.0,
+                ..Input::default()
+            };
+            let last_pos = world.player.pos;
+            world.step(TICK_DT, &input);
+            if tick > 0 {
+                distance += last_pos.distance(world.player.pos);
+            }
+            let events = match tick {
+                0 => vec![Event::Shot, Event::Shot, Event::Shot],
+                60 => vec![Event::Collision, Event::Killed(killed_at[0])],
+                119 => vec![Event::Shot, Event::Hit],
+                120 => vec![Event::Killed(killed_at[1]), Event::Collision],
+                239 => vec![Event::Collision, Event::TimeUp],
+                _ => vec![],
+            };
+            stats.record(&world, &input, &events);
+        }
+
+        assert_eq!(stats.seconds.len(), 2);
+        let [first, second] = [stats.seconds[0], stats.seconds[1]];
+        assert_eq!((first.shots, first.kills, first.collisions), (4, 1, 1));
+        assert_eq!((second.shots, second.kills, second.collisions), (0, 1, 2));
+        assert!((first.fighting - 1.0).abs() < 1e-3, "{:?}", first);
+        assert!(first.riding.abs() < 1e-3, "{:?}", first);
+        assert!(second.fighting.abs() < 1e-3, "{:?}", second);
+        assert!((second.riding - 1.0).abs() < 1e-3, "{:?}", second);
+        assert_eq!(stats.kills, killed_at);
+
+        let totals = stats.totals();
+        assert_eq!((totals.shots, totals.kills, totals.collisions), (4, 2, 3));
+        assert!((totals.fighting + totals.riding - 2.0).abs() < 1e-3);
+        assert!(totals.distance > 0.0);
+        assert!((totals.distance - (first.distance + second.distance)).abs() < 1e-3);
+        assert!((totals.distance - distance).abs() < 1e-2, "{:?}", totals);
+        assert_eq!(stats.heat.iter().sum::<u32>(), 2 * ticks);
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::map::Map;
+use crate::mode::GameMode;
+use crate::replay::Replay;
+use crate::scoring::Breakdown;
+use crate::sim::{World, TICK_DT};
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Score {
+    pub map: String,
+    /// time attack for scores from before there were modes
+    #[serde(default)]
+    pub mode: GameMode,
+    pub name: String,
+    pub score: i32,
+}
+
+/// What the game posts to `/newScore`: the score and how it was made up,
+/// plus the compact replay that proves it.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Submission {
+    pub map: String,
+    #[serde(default)]
+    pub mode: GameMode,
+    pub name: String,
+    pub score: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub breakdown: Option<Breakdown>,
+    pub replay: String,
+}
+
+impl Submission {
+    pub fn new(name: &str, breakdown: &Breakdown, replay: &Replay) -> Submission {
+        Submission {
+            map: replay.map.clone(),
+            mode: replay.mode,
+            name: name.to_owned(),
+            score: breakdown.score,
+            breakdown: Some(breakdown.clone()),
+            replay: replay.to_compact(),
+        }
+    }
+
+    pub fn score(&self) -> Score {
+        Score {
+            map: self.map.clone(),
+            mode: self.mode,
+            name: self.name.clone(),
+            score: self.score,
+        }
+    }
+}
+
+/// Plays the replay through the simulation and returns the score the run
+/// actually got. The inputs have to cover the run exactly, up to the tick
+/// the timer runs out.
+pub fn replay_score(replay: &Replay, maps: &[Map]) -> anyhow::Result<i32> {
+    Ok(replay_run(replay, maps)?.score())
+}
+
+/// The world as it was at the end of the replayed run.
+pub fn replay_run(replay: &Replay, maps: &[Map]) -> anyhow::Result<World> {
+    let mut world = replay.world(maps)?;
+    if replay.num_ticks() > replay.mode.max_ticks(&world.map) {
+        bail!("replay is longer than a run on {} can be", replay.map);
+    }
+    for (tick, input) in replay.inputs().enumerate() {
+        if !world.playing {
+            bail!("replay has inputs after the run ended at tick {}", tick);
+        }
+        world.step(TICK_DT, &input);
+    }
+    if world.playing {
+        bail!("replay ends before the run does");
+    }
+    Ok(world)
+}
+
+/// Accepts a submission only if its replay is for the same map, one of
+/// `maps`, and mode, and really scores what it claims.
+pub fn verify(submission: &Submission, maps: &[Map]) -> anyhow::Result<i32> {
+    let replay = Replay::from_compact(&submission.replay, maps)?;
+    if replay.map != submission.map {
+        bail!(
+            "score is for {} but the replay is for {}",
+            submission.map,
+            replay.map
+        );
+    }
+    if replay.mode != submission.mode {
+        bail!(
+            "score is for {} but the replay is for {}",
+            submission.mode.name(),
+            replay.mode.name()
+        );
+    }
+    let world = replay_run(&replay, maps)?;
+    let score = world.score();
+    if score != submission.score {
+        bail!(
+            "claimed a score of {} but the replay scores {}",
+            submission.score,
+            score
+        );
+    }
+    if submission
+        .breakdown
+        .as_ref()
+        .is_some_and(|breakdown| *breakdown != Breakdown::new(&world))
+    {
+        bail!("the score's breakdown doesn't match the replay");
+    }
+    Ok(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::Vec2;
+
+    use super::*;
+    use crate::field::{Field, Square};
+    use crate::sim::Input;
+
+    /// A map with no field and a one second time limit, so runs on it are
+    /// quick to play.
+    fn short_map() -> Map {
+        let mut map = Map::new(
+            "short",
+            Field::Square(Square {
+                scale: 0.0,
+                c: [0.0, 0.0],
+            }),
+        );
+        map.time_limit = 1.0;
+        map
+    }
+
+    /// Plays a whole run on `map`, firing at the middle of the arena, and
+    /// returns its replay and the score it would be submitted with.
+    fn play(map: &Map, mode: GameMode) -> (Replay, Submission) {
+        let mut world = World::new(map.clone(), 3);
+        world.mode = mode;
+        world.start();
+        let mut replay = Replay::new(3, map, mode);
+        let mut tick = 0;
+        while world.playing {
+            let input = Input {
+                up: tick % 60 < 30,
+                aim: world.arena / 2.0 + Vec2::new(tick as f32, 0.0),
+                fire: tick % 10 == 0,
+                ..Input::default()
+            };
+            world.step(TICK_DT, &input);
+            replay.push(&input);
+            tick += 1;
+        }
+        let submission = Submission::new("ab", &Breakdown::new(&world), &replay);
+        (replay, submission)
+    }
+
+    #[test]
+    fn accepts_real_scores() {
+        let maps = [short_map()];
+        for mode in [GameMode::TimeAttack, GameMode::TargetRun] {
+            let (replay, submission) = play(&maps[0], mode);
+            assert_eq!(verify(&submission, &maps).unwrap(), submission.score);
+            assert_eq!(replay_score(&replay, &maps).unwrap(), submission.score);
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_scores() {
+        let maps = [short_map(), Map::new("other", short_map().field)];
+        let (_, submission) = play(&maps[0], GameMode::TimeAttack);
+
+        let mut tampered = submission.clone();
+        tampered.score += 1;
+        assert!(verify(&tampered, &maps).is_err());
+
+        let mut tampered = submission.clone();
+        if let Some(breakdown) = &mut tampered.breakdown {
+            breakdown.curve_bonus += 100;
+        }
+        assert!(verify(&tampered, &maps).is_err());
+
+        let mut tampered = submission.clone();
+        tampered.map = "other".to_owned();
+        assert!(verify(&tampered, &maps).is_err());
+
+        let mut tampered = submission.clone();
+        tampered.mode = GameMode::Survival;
+        assert!(verify(&tampered, &maps).is_err());
+
+        // and ones for maps it doesn't know
+        assert!(verify(&submission, &maps[1..]).is_err());
+    }
+
+    #[test]
+    fn rejects_replays_that_dont_cover_the_run() {
+        let maps = [short_map()];
+        let (replay, submission) = play(&maps[0], GameMode::TimeAttack);
+
+        let mut longer = replay.clone();
+        longer.push(&Input::default());
+        let tampered = Submission {
+            replay: longer.to_compact(),
+            ..submission.clone()
+        };
+        assert!(verify(&tampered, &maps).is_err());
+        assert!(replay_score(&longer, &maps).is_err());
+
+        let mut shorter = replay.clone();
+        shorter.inputs.last_mut().unwrap().ticks -= 1;
+        let tampered = Submission {
+            replay: shorter.to_compact(),
+            ..submission
+        };
+        assert!(verify(&tampered, &maps).is_err());
+        assert!(replay_score(&shorter, &maps).is_err());
+    }
+
+    #[test]
+    fn rejects_replays_longer_than_a_run_can_be() {
+        let maps = [short_map()];
+        let mut replay = Replay::new(3, &maps[0], GameMode::TimeAttack);
+        replay.push(&Input::default());
+        for ticks in [1000, u32::MAX] {
+            replay.inputs[0].ticks = ticks;
+            let err = Replay::from_compact(&replay.to_compact(), &maps).unwrap_err();
+            assert!(err.to_string().contains("longer"), "{}", err);
+            let err = replay_score(&replay, &maps).unwrap_err();
+            assert!(err.to_string().contains("longer"), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use anyhow::bail;
+use macroquad::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::rng::Rng;
+
+/// How long before an enemy comes in that its spot on the edge is marked.
+pub const TELEGRAPH_SECS: f32 = 1.0;
+
+/// A map's script of what spawns when, instead of keeping the arena topped
+/// up to `enemies.max`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Waves {
+    pub waves: Vec<Wave>,
+    /// keep going through the script, harder each time, until the player
+    /// dies, instead of stopping at the time limit
+    #[serde(default)]
+    pub endless: bool,
+    /// in endless mode, seconds between the last wave and going round again
+    #[serde(default = "default_rest")]
+    pub rest: f32,
+    /// in endless mode, how many more enemies each spawn has every time
+    /// round, as a fraction of the first time
+    #[serde(default = "default_ramp")]
+    pub ramp: f32,
+}
+
+impl Waves {
+    /// Fails for times the script can't be played with, like a wave before
+    /// the run starts, which in endless mode would start over and over.
+    pub fn check(&self) -> anyhow::Result<()> {
+        let not_negative = |secs: f32| secs >= 0.0 && secs.is_finite();
+        if !not_negative(self.rest) || !not_negative(self.ramp) {
+            bail!(
+                "waves' rest and ramp can't be negative, not {} and {}",
+                self.rest,
+                self.ramp
+            );
+        }
+        for wave in &self.waves {
+            if !not_negative(wave.at) {
+                bail!("a wave can't start at {} seconds", wave.at);
+            }
+            for spawn in &wave.spawns {
+                if !not_negative(spawn.interval) {
+                    bail!(
+                        "enemies in a wave can't come in {} seconds apart",
+                        spawn.interval
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_rest() -> f32 {
+    5.0
+}
+
+fn default_ramp() -> f32 {
+    0.5
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Wave {
+    /// seconds into the run (or into each time round the script)
+    pub at: f32,
+    pub spawns: Vec<Spawn>,
+}
+
+/// A group of the same enemy coming in from the same edge.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Spawn {
+    /// which of the map's enemy types, counting from 0; a plain enemy if
+    /// left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enemy: Option<usize>,
+    #[serde(default = "default_count")]
+    pub count: u32,
+    /// seconds between each one
+    #[serde(default)]
+    pub interval: f32,
+    #[serde(default)]
+    pub edge: Edge,
+    /// how far along the edge they come in, from 0 at the top or left to 1
+    /// at the bottom or right; anywhere if left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub along: Option<f32>,
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Edge {
+    #[default]
+    Any,
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Edge {
+    /// A point just outside the arena on this edge, far enough out that an
+    /// enemy of `radius` there is out of sight. Random edges and positions
+    /// come from `rng`.
+    pub fn point(self, along: Option<f32>, radius: f32, arena: Vec2, rng: &mut Rng) -> Vec2 {
+        let edge = match self {
+            Edge::Any => {
+                [Edge::Left, Edge::Right, Edge::Top, Edge::Bottom][(rng.rand() % 4) as usize]
+            }
+            edge => edge,
+        };
+        let length = match edge {
+            Edge::Left | Edge::Right => arena.y,
+            _ => arena.x,
+        };
+        let offset = match along {
+            Some(along) => along.clamp(0.0, 1.0) * length,
+            None => (rng.rand() % length as u32) as f32,
+        };
+        match edge {
+            Edge::Left => Vec2::new(-radius, offset),
+            Edge::Right => Vec2::new(arena.x + radius, offset),
+            Edge::Top => Vec2::new(offset, -radius),
+            _ => Vec2::new(offset, arena.y + radius),
+        }
+    }
+}
+
+/// An enemy that's about to come in, marked on the edge of the arena.
+#[derive(Clone, Debug)]
+pub struct Telegraph {
+    pub pos: Vec2,
+    pub enemy: Option<usize>,
+    pub secs_left: f32,
+}
+
+/// How far through the script a run is.
+#[derive(Clone, Debug, Default)]
+pub struct WaveState {
+    /// the next wave to start
+    pub wave: usize,
+    /// how many times the script has been gone through
+    pub round: u32,
+    /// spawns from waves that have started: when each is due, and which
+    pending: Vec<(f32, Spawn)>,
+}
+
+impl WaveState {
+    /// The spawns due by `time`, after starting any waves whose time has
+    /// come. Each of them is one enemy.
+    pub fn due(&mut self, waves: &Waves, time: f32) -> Vec<Spawn> {
+        while let Some(wave) = waves.waves.get(self.wave) {
+            let start = self.round_start(waves) + wave.at;
+            if start > time {
+                break;
+            }
+            let scale = 1.0 + waves.ramp * self.round as f32;
+            for spawn in &wave.spawns {
+                let count = (spawn.count as f32 * scale).round() as u32;
+                for i in 0..count {
+                    let at = start + i as f32 * spawn.interval;
+                    self.pending.push((at, spawn.clone()));
+                }
+            }
+            self.wave += 1;
+            if self.wave == waves.waves.len() && waves.endless {
+                self.wave = 0;
+                self.round += 1;
+            }
+        }
+        // keep them in the order they were scheduled in, so runs play out
+        // the same every time
+        let mut due = vec![];
+        self.pending.retain(|(at, spawn)| {
+            if *at <= time {
+                due.push(spawn.clone());
+                return false;
+            }
+            true
+        });
+        due
+    }
+
+    /// How many waves have started, counting every time round.
+    pub fn started(&self, waves: &Waves) -> usize {
+        self.round as usize * waves.waves.len() + self.wave
+    }
+
+    fn round_start(&self, waves: &Waves) -> f32 {
+        let last = waves.waves.iter().map(|wave| wave.at).fold(0.0, f32::max);
+        // at least a second a round, so a script that's all at 0 doesn't
+        // start every round at once
+        self.round as f32 * (last + waves.rest).max(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wave(at: f32, count: u32, interval: f32) -> Wave {
+        Wave {
+            at,
+            spawns: vec![Spawn {
+                enemy: None,
+                count,
+                interval,
+                edge: Edge::Any,
+                along: None,
+            }],
+        }
+    }
+
+    /// How many spawns come due on each tenth of a second from 0 until
+    /// `secs`, for the ones where any do.
+    fn schedule(waves: &Waves, secs: u32) -> Vec<(u32, usize)> {
+        let mut state = WaveState::default();
+        (0..=secs * 10)
+            .map(|tenth| (tenth, state.due(waves, tenth as f32 / 10.0).len()))
+            .filter(|&(_, due)| due > 0)
+            .collect()
+    }
+
+    #[test]
+    fn spawns_each_wave_on_time() {
+        let waves = Waves {
+            waves: vec![wave(0.0, 2, 0.5), wave(2.0, 1, 0.0)],
+            endless: false,
+            rest: default_rest(),
+            ramp: default_ramp(),
+        };
+        assert_eq!(schedule(&waves, 10), [(0, 1), (5, 1), (20, 1)]);
+
+        let mut state = WaveState::default();
+        state.due(&waves, 1.0);
+        assert_eq!(state.started(&waves), 1);
+        // everything that's due comes at once, in order
+        assert_eq!(state.due(&waves, 100.0).len(), 1);
+        assert_eq!(state.started(&waves), 2);
+    }
+
+    #[test]
+    fn endless_scripts_go_round_again_with_more_enemies() {
+        let waves = Waves {
+            waves: vec![wave(0.0, 2, 0.5), wave(2.0, 1, 0.0)],
+            endless: true,
+            rest: 3.0,
+            ramp: 0.5,
+        };
+        // a round every 5 seconds: the last wave's 2 plus the rest, with
+        // 1.5 times as many enemies the second time and twice as many the
+        // third
+        assert_eq!(
+            schedule(&waves, 12),
+            [
+                (0, 1),
+                (5, 1),
+                (20, 1),
+                (50, 1),
+                (55, 1),
+                (60, 1),
+                (70, 2),
+                (100, 1),
+                (105, 1),
+                (110, 1),
+                (115, 1),
+                (120, 2),
+            ]
+        );
+        let mut state = WaveState::default();
+        state.due(&waves, 10.0);
+        assert_eq!(state.round, 2);
+        assert_eq!(state.started(&waves), 5);
+    }
+
+    #[test]
+    fn checks_times() {
+        let waves = |at: f32, interval: f32| Waves {
+            waves: vec![wave(at, 2, interval)],
+            endless: true,
+            rest: default_rest(),
+            ramp: default_ramp(),
+        };
+        assert!(waves(0.0, 0.0).check().is_ok());
+        assert!(waves(-1e9, 0.0).check().is_err());
+        assert!(waves(f32::NAN, 0.0).check().is_err());
+        assert!(waves(0.0, -1.0).check().is_err());
+        assert!(waves(0.0, f32::INFINITY).check().is_err());
+        let mut rest = waves(0.0, 0.0);
+        rest.rest = -10.0;
+        assert!(rest.check().is_err());
+    }
+
+    #[test]
+    fn points_are_just_outside_the_edge() {
+        let arena = Vec2::new(100.0, 50.0);
+        let mut rng = Rng::new(1);
+        let mut point = |edge: Edge, along| edge.point(along, 10.0, arena, &mut rng);
+        assert_eq!(point(Edge::Left, Some(0.5)), Vec2::new(-10.0, 25.0));
+        assert_eq!(point(Edge::Right, Some(0.0)), Vec2::new(110.0, 0.0));
+        assert_eq!(point(Edge::Top, Some(1.0)), Vec2::new(100.0, -10.0));
+        assert_eq!(point(Edge::Bottom, Some(2.0)), Vec2::new(100.0, 60.0));
+        for _ in 0..20 {
+            let pos = point(Edge::Any, None);
+            let outside = [
+                pos.x == -10.0,
+                pos.x == 110.0,
+                pos.y == -10.0,
+                pos.y == 60.0,
+            ];
+            assert_eq!(outside.iter().filter(|&&edge| edge).count(), 1, "{}", pos);
+            assert!((-10.0..=110.0).contains(&pos.x) && (-10.0..=60.0).contains(&pos.y));
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use macroquad::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::physics::Physics;
+use crate::sim::{Body, PROJECTILE_INIT_SPEED};
+
+/// How long a projectile lasts before it fizzles out, for ones that never
+/// leave the arena.
+pub const PROJECTILE_LIFETIME: f32 = 5.0;
+/// How hard seeking shots turn toward their target.
+pub const HOMING_ACCEL: f32 = 4000.0;
+/// How far away seeking shots notice enemies.
+pub const HOMING_RANGE: f32 = 400.0;
+
+/// How a projectile moves once it's fired.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectileKind {
+    Standard,
+    /// barely pushed around by the field
+    Heavy,
+    /// slows down until it's just drifting along with the field
+    Light,
+    /// bounces off the edges of the arena and off obstacles
+    Bouncing,
+    /// curves toward the nearest enemy
+    Homing,
+}
+
+impl ProjectileKind {
+    /// What a weapon's projectiles of this kind get unless it says otherwise.
+    pub fn physics(self) -> Physics {
+        let standard = Physics::projectile();
+        match self {
+            ProjectileKind::Heavy => Physics {
+                mass: 5.0,
+                ..standard
+            },
+            ProjectileKind::Light => Physics {
+                coupling: 3.0 * standard.coupling,
+                drag: 3.0,
+                ..standard
+            },
+            _ => standard,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Projectile {
+    pub body: Body,
+    pub kind: ProjectileKind,
+    /// seconds until it fizzles out
+    pub life: f32,
+    /// radians the field has turned it through so far
+    pub turned: f32,
+    /// whether it's hit an enemy yet, so a shot that goes through several
+    /// only counts once toward accuracy
+    pub hit: bool,
+}
+
+impl Projectile {
+    pub fn new(pos: Vec2, vel: Vec2, kind: ProjectileKind) -> Projectile {
+        Projectile {
+            body: Body::new(pos, vel).with_physics(kind.physics()),
+            kind,
+            life: PROJECTILE_LIFETIME,
+            turned: 0.0,
+            hit: false,
+        }
+    }
+}
+
+/// Something the player can fire. Holding the trigger fires a shot every
+/// `cooldown` seconds while there's ammo left.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Weapon {
+    pub name: String,
+    pub kind: ProjectileKind,
+    /// seconds between shots
+    pub cooldown: f32,
+    /// how many shots there are for a run; unlimited if left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ammo: Option<u32>,
+    /// projectiles per shot
+    #[serde(default = "default_pellets")]
+    pub pellets: u32,
+    /// degrees between the outermost projectiles of a shot, which are fanned
+    /// out evenly
+    #[serde(default)]
+    pub spread: f32,
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    /// overrides the usual physics for the kind of projectile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub physics: Option<Physics>,
+}
+
+fn default_pellets() -> u32 {
+    1
+}
+
+fn default_speed() -> f32 {
+    PROJECTILE_INIT_SPEED
+}
+
+impl Weapon {
+    fn new(name: &str, kind: ProjectileKind, cooldown: f32, ammo: Option<u32>) -> Weapon {
+        Weapon {
+            name: name.to_owned(),
+            kind,
+            cooldown,
+            ammo,
+            pellets: 1,
+            spread: 0.0,
+            speed: PROJECTILE_INIT_SPEED,
+            physics: None,
+        }
+    }
+
+    /// The projectiles of one shot from `from` toward `aim`.
+    pub fn fire(&self, from: Vec2, aim: Vec2) -> impl Iterator<Item = Projectile> + '_ {
+        let dir = (aim - from).normalize_or(Vec2::X);
+        let pellets = self.pellets.max(1);
+        (0..pellets).map(move |i| {
+            let t = if pellets == 1 {
+                0.5
+            } else {
+                i as f32 / (pellets - 1) as f32
+            };
+            let angle = (t - 0.5) * self.spread.to_radians();
+            let vel = Vec2::from_angle(angle).rotate(dir) * self.speed;
+            let mut projectile = Projectile::new(from, vel, self.kind);
+            if let Some(physics) = self.physics {
+                projectile.body.physics = physics;
+            }
+            projectile
+        })
+    }
+}
+
+/// What maps give the player unless they say otherwise, on keys 1 to 5.
+pub fn default_weapons() -> Vec<Weapon> {
+    vec![
+        Weapon::new("blaster", ProjectileKind::Standard, 0.05, None),
+        Weapon::new("cannon", ProjectileKind::Heavy, 0.4, Some(20)),
+        Weapon {
+            pellets: 5,
+            spread: 30.0,
+            ..Weapon::new("scatter", ProjectileKind::Light, 0.3, Some(30))
+        },
+        Weapon::new("ricochet", ProjectileKind::Bouncing, 0.15, Some(60)),
+        Weapon::new("seeker", ProjectileKind::Homing, 0.25, Some(40)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scatter() -> Weapon {
+        default_weapons()
+            .into_iter()
+            .find(|weapon| weapon.name == "scatter")
+            .unwrap()
+    }
+
+    #[test]
+    fn fires_one_projectile_at_the_aim() {
+        let blaster = &default_weapons()[0];
+        let from = Vec2::new(100.0, 100.0);
+        let shot: Vec<Projectile> = blaster.fire(from, Vec2::new(100.0, 300.0)).collect();
+        assert_eq!(shot.len(), 1);
+        assert_eq!(shot[0].body.pos, from);
+        assert!(shot[0].body.vel.distance(Vec2::new(0.0, blaster.speed)) < 1e-3);
+        assert_eq!(shot[0].kind, ProjectileKind::Standard);
+        // aiming at itself still fires somewhere
+        let shot: Vec<Projectile> = blaster.fire(from, from).collect();
+        assert_eq!(shot[0].body.vel, Vec2::new(blaster.speed, 0.0));
+    }
+
+    #[test]
+    fn fans_pellets_out_across_the_spread() {
+        let scatter = scatter();
+        let shot: Vec<Projectile> = scatter.fire(Vec2::ZERO, Vec2::new(1.0, 0.0)).collect();
+        assert_eq!(shot.len(), 5);
+        let angles: Vec<f32> = shot
+            .iter()
+            .map(|projectile| projectile.body.vel.to_angle().to_degrees())
+            .collect();
+        for (angle, expected) in angles.iter().zip([-15.0, -7.5, 0.0, 7.5, 15.0]) {
+            assert!((angle - expected).abs() < 1e-3, "{:?}", angles);
+        }
+        for projectile in &shot {
+            assert!((projectile.body.vel.length() - scatter.speed).abs() < 1e-2);
+            assert_eq!(projectile.body.physics, ProjectileKind::Light.physics());
+        }
+    }
+
+    #[test]
+    fn uses_its_own_physics() {
+        let physics = Physics {
+            mass: 2.0,
+            ..Physics::default()
+        };
+        let weapon = Weapon {
+            physics: Some(physics),
+            ..scatter()
+        };
+        assert!(weapon
+            .fire(Vec2::ZERO, Vec2::X)
+            .all(|projectile| projectile.body.physics == physics));
+    }
+}
@@ -0,0 +1,152 @@
+//! Runs `flowfield-verify --serve` and talks to it over HTTP.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use flowfield::map::{builtin_maps, find_map};
+use flowfield::mode::GameMode;
+use flowfield::replay::Replay;
+use flowfield::scoring::Breakdown;
+use flowfield::sim::{Input, World, TICK_DT};
+use flowfield::verify::Submission;
+
+/// The server, killed when the test is done with it, pass or fail.
+struct Server {
+    child: Child,
+    addr: String,
+}
+
+impl Server {
+    fn start() -> Server {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_flowfield-verify"))
+            .args(["--serve", "127.0.0.1:0"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("couldn't start the server");
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        let addr = line
+            .trim()
+            .strip_prefix("listening on ")
+            .unwrap_or_else(|| panic!("unexpected first line {:?}", line))
+            .to_owned();
+        // keep reading what it logs, so it never blocks on a full pipe
+        std::thread::spawn(move || std::io::copy(&mut stdout, &mut std::io::sink()));
+        Server { child, addr }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A whole time attack run on a built-in map, as it would be submitted.
+fn submission() -> Submission {
+    let maps = builtin_maps();
+    let map = find_map(&maps, "dual vision").unwrap();
+    let mut world = World::new(map.clone(), 5);
+    world.start();
+    let mut replay = Replay::new(5, map, GameMode::TimeAttack);
+    replay.map_def = None;
+    let mut tick = 0;
+    while world.playing {
+        let input = Input {
+            left: tick % 240 < 120,
+            aim: world.arena / 2.0,
+            fire: tick % 12 == 0,
+            ..Input::default()
+        };
+        world.step(TICK_DT, &input);
+        replay.push(&input);
+        tick += 1;
+    }
+    Submission::new("ab", &Breakdown::new(&world), &replay)
+}
+
+fn post(client: &Client, url: &str, body: &Value) -> (StatusCode, String) {
+    let response = client.post(url).body(body.to_string()).send().unwrap();
+    (response.status(), response.text().unwrap())
+}
+
+#[test]
+fn verifies_and_keeps_scores() {
+    let server = Server::start();
+    let client = Client::new();
+    let submission = submission();
+
+    let (status, body) = post(&client, &server.url("/verify"), &json!(submission));
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let verified: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(verified["score"], submission.score);
+
+    let mut tampered = submission.clone();
+    tampered.score += 1000;
+    for path in ["/verify", "/newScore"] {
+        let (status, body) = post(&client, &server.url(path), &json!(tampered));
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let rejected: Value = serde_json::from_str(&body).unwrap();
+        assert!(
+            rejected["error"].as_str().unwrap().contains("score"),
+            "{}",
+            body
+        );
+    }
+
+    let (status, body) = post(&client, &server.url("/newScore"), &json!(submission));
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let response = client.get(server.url("/topScores")).send().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let top_scores: Value = serde_json::from_str(&response.text().unwrap()).unwrap();
+    let board = &top_scores[GameMode::TimeAttack.leaderboard("dual vision")];
+    assert_eq!(
+        board,
+        &json!([{
+            "map": "dual vision",
+            "mode": "time_attack",
+            "name": "ab",
+            "score": submission.score,
+        }])
+    );
+    assert_eq!(
+        top_scores[GameMode::Survival.leaderboard("dual vision")],
+        json!([])
+    );
+
+    let response = client.get(server.url("/nowhere")).send().unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn turns_away_huge_requests() {
+    let server = Server::start();
+    // without sending the body, which the server shouldn't wait for
+    let mut stream = TcpStream::connect(&server.addr).unwrap();
+    write!(
+        stream,
+        "POST /verify HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n\r\n",
+        server.addr,
+        1u64 << 40
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 413 Payload Too Large"),
+        "{}",
+        response
+    );
+    assert!(response.contains(r#""error""#), "{}", response);
+}